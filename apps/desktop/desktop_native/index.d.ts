@@ -5,19 +5,178 @@
 
 export namespace passwords {
   /** Fetch the stored password from the keychain. */
-  export function getPassword(service: string, account: string): Promise<string>
+  export function getPassword(service: string, account: string, options?: KeychainOptions | undefined | null): Promise<string>
   /** Fetch the stored password from the keychain that was stored with Keytar. */
-  export function getPasswordKeytar(service: string, account: string): Promise<string>
-  /** Save the password to the keychain. Adds an entry if none exists otherwise updates the existing entry. */
-  export function setPassword(service: string, account: string, password: string): Promise<void>
+  export function getPasswordKeytar(service: string, account: string, options?: KeychainOptions | undefined | null): Promise<string>
+  /**
+   * Fetch the stored password from the keychain along with its metadata, as far as the
+   * platform keeps it.
+   */
+  export function getEntry(service: string, account: string, options?: KeychainOptions | undefined | null): Promise<KeychainEntry>
+  /**
+   * Save the password to the keychain. Adds an entry if none exists otherwise updates the existing entry.
+   * The label and comment replace those of an existing entry, where the platform supports them.
+   * Without metadata they are reset to the defaults.
+   */
+  export function setPassword(service: string, account: string, password: string, metadata?: EntryMetadata | undefined | null, options?: KeychainOptions | undefined | null): Promise<void>
+  /**
+   * Save the password to the keychain unless it is already stored, resolving to whether it was
+   * written.
+   */
+  export function setPasswordIfChanged(service: string, account: string, password: string, options?: KeychainOptions | undefined | null): Promise<boolean>
+  /**
+   * Save the password to the keychain only if the stored password equals `expected`, where
+   * `null` expects no entry to exist. Resolves to whether it was written.
+   */
+  export function compareAndSwap(service: string, account: string, expected: string | undefined | null, password: string, options?: KeychainOptions | undefined | null): Promise<boolean>
+  /** Fetch the stored secret from the keychain as raw bytes. */
+  export function getSecretBytes(service: string, account: string, options?: KeychainOptions | undefined | null): Promise<Buffer>
+  /**
+   * Save the secret to the keychain as raw bytes, so key material doesn't have to be encoded
+   * as a string. Adds an entry if none exists otherwise updates the existing entry.
+   */
+  export function setSecretBytes(service: string, account: string, secret: Buffer, options?: KeychainOptions | undefined | null): Promise<void>
   /** Delete the stored password from the keychain. */
-  export function deletePassword(service: string, account: string): Promise<void>
+  export function deletePassword(service: string, account: string, options?: KeychainOptions | undefined | null): Promise<void>
+  /**
+   * List the accounts of every service that starts with the prefix, e.g. to find the entries of
+   * logged out users. An empty prefix is rejected.
+   */
+  export function listAccounts(servicePrefix: string, options?: KeychainOptions | undefined | null): Promise<Array<Account>>
+  /**
+   * Delete the passwords of every service that starts with the prefix, resolving to the number
+   * of deleted entries. An empty prefix is rejected.
+   */
+  export function deleteAll(servicePrefix: string, options?: KeychainOptions | undefined | null): Promise<number>
+  export interface KeychainOptions {
+    /**
+     * Reject with a `Timeout` error when the operation takes longer, in milliseconds. The
+     * keychain may be waiting for the user to unlock it.
+     */
+    timeout?: number
+    /** Reject with a `Cancelled` error when the signal is aborted. */
+    signal?: AbortSignal
+  }
+  export interface KeychainEntry {
+    password: string
+    /** In milliseconds since the Unix epoch. Not recorded by Credential Manager on Windows. */
+    created?: number
+    /** In milliseconds since the Unix epoch. */
+    modified?: number
+    /** Not supported by Credential Manager on Windows. */
+    label?: string
+    comment?: string
+  }
+  export interface EntryMetadata {
+    /** Shown by keychain managers instead of the service and account. */
+    label?: string
+    comment?: string
+  }
+  export interface Account {
+    service: string
+    account: string
+  }
+  /**
+   * Select where the keychain functions store passwords. The encrypted file backend requires
+   * the path of the file and a 64 byte key, the fallback backend generates its own key.
+   */
+  export function setCredentialBackend(backend: CredentialBackend, path?: string | undefined | null, key?: Buffer | undefined | null): void
+  export const enum CredentialBackend {
+    /** libsecret on Linux, Keychain on macOS and Credential Manager on Windows. */
+    Os = 0,
+    /** Kept in memory only, nothing is persisted. */
+    Memory = 1,
+    /** A single encrypted file. */
+    EncryptedFile = 2,
+    /**
+     * The encrypted file under `$XDG_DATA_HOME` that Linux uses when no Secret Service
+     * provider is running.
+     */
+    Fallback = 3
+  }
+  /**
+   * Whether passwords are currently stored in the Linux fallback file instead of the
+   * keychain, either because it was selected or because no Secret Service provider is running.
+   */
+  export function usesFallbackStore(): boolean
+  /** The `code` of the errors thrown by the keychain functions, stable across platforms. */
+  export const enum KeychainErrorCode {
+    NotFound = 'NotFound',
+    AccessDenied = 'AccessDenied',
+    Locked = 'Locked',
+    BackendUnavailable = 'BackendUnavailable',
+    InvalidData = 'InvalidData',
+    Timeout = 'Timeout',
+    Cancelled = 'Cancelled',
+    Other = 'Other'
+  }
+}
+export namespace crypto {
+  /** Parse a CipherString into its encryption type and decoded parts. */
+  export function parseCipherString(cipherString: string): ParsedCipherString
+  /** Check whether the string is a well formed CipherString. */
+  export function isValidCipherString(cipherString: string): boolean
+  /**
+   * Encrypt the data with a random IV. A 32 byte key produces a type 0 CipherString, a 64 byte
+   * key (encryption key followed by the MAC key) produces a type 2 CipherString.
+   */
+  export function encrypt(data: Buffer, key: Buffer): Promise<string>
+  /** Decrypt a type 0 or type 2 CipherString. The key follows the same layout as in `encrypt`. */
+  export function decrypt(cipherString: string, key: Buffer): Promise<Buffer>
+  /**
+   * Decrypt many type 0 or type 2 CipherStrings to UTF-8 strings in parallel. Each item gets its
+   * own result, so a single corrupt CipherString does not fail the whole batch.
+   */
+  export function decryptBatch(cipherStrings: Array<string>, key: Buffer): Promise<Array<DecryptResult>>
+  /** Encrypt the data with a DER encoded SPKI public key, producing a type 4 CipherString. */
+  export function encryptRsa(data: Buffer, publicKey: Buffer): Promise<string>
+  /** Decrypt a RSA CipherString with a DER encoded PKCS#8 private key. */
+  export function decryptRsa(cipherString: string, privateKey: Buffer): Promise<Buffer>
+  /**
+   * Derive the master key from the master password. Runs on a blocking thread since the KDF
+   * is deliberately slow.
+   */
+  export function deriveMasterKey(password: string, saltEmail: string, kdfConfig: KdfConfig): Promise<Buffer>
+  /**
+   * Encrypt the file at `input_path` into `output_path` in the `EncArrayBuffer` format, used for
+   * attachments and file Sends. The key follows the same layout as in `encrypt`.
+   */
+  export function encryptFile(inputPath: string, outputPath: string, key: Buffer, progress?: (processed: number, total: number) => void): Promise<void>
+  /**
+   * Decrypt an `EncArrayBuffer` file at `input_path` into `output_path`. The MAC is verified
+   * before any plaintext is written, and an existing output is only replaced once decryption
+   * succeeded. The input can't be the output.
+   */
+  export function decryptFile(inputPath: string, outputPath: string, key: Buffer, progress?: (processed: number, total: number) => void): Promise<void>
+  /** Mirrors `KdfType` in `libs/common`. */
+  export const enum KdfType {
+    Pbkdf2Sha256 = 0,
+    Argon2id = 1
+  }
+  /** Mirrors `KdfConfig` in `libs/common`. Memory is in MiB. */
+  export interface KdfConfig {
+    kdfType: KdfType
+    iterations: number
+    memory?: number
+    parallelism?: number
+  }
+  /** Result of a single item in `decrypt_batch`, exactly one of the fields is set. */
+  export interface DecryptResult {
+    data?: string
+    error?: string
+  }
+  export interface ParsedCipherString {
+    encType: number
+    iv?: Buffer
+    mac?: Buffer
+    data: Buffer
+  }
 }
 export namespace biometrics {
-  export function prompt(hwnd: Buffer, message: string): Promise<boolean>
-  export function available(): Promise<boolean>
+  export function prompt(hwnd: Buffer, message: string): Promise<PromptOutcome>
+  export function available(): Promise<BiometricStatus>
   export function setBiometricSecret(service: string, account: string, secret: string, keyMaterial: KeyMaterial | undefined | null, ivB64: string): Promise<string>
-  export function getBiometricSecret(service: string, account: string, keyMaterial?: KeyMaterial | undefined | null): Promise<string>
+  export function getBiometricSecret(service: string, account: string, keyMaterial?: KeyMaterial | undefined | null, options?: BiometricSecretOptions | undefined | null): Promise<string>
   /**
    * Derives key material from biometric data. Returns a string encoded with a
    * base64 encoded key and the base64 encoded challenge used to create it
@@ -32,8 +191,47 @@ export namespace biometrics {
     osKeyPartB64: string
     clientKeyPartB64?: string
   }
+  export interface BiometricSecretOptions {
+    /** Refuse secrets stored unencrypted, rather than returning them. */
+    strict?: boolean
+    /**
+     * The challenge the key material was derived from. Secrets stored unencrypted are only
+     * encrypted when read if it's provided.
+     */
+    ivB64?: string
+  }
   export interface OsDerivedKey {
     keyB64: string
     ivB64: string
   }
+  /** How a biometric prompt ended. */
+  export const enum PromptOutcome {
+    Verified = 'Verified',
+    /** The user dismissed the prompt. */
+    Canceled = 'Canceled',
+    /** The user failed to verify too many times. */
+    RetriesExhausted = 'RetriesExhausted',
+    /** The device is in use, e.g. by another prompt. */
+    DeviceBusy = 'DeviceBusy',
+    /** The device is present, but the user hasn't set it up. */
+    NotConfigured = 'NotConfigured',
+    /** There is no device to verify with, or it's disabled. */
+    Unavailable = 'Unavailable'
+  }
+  /** Why biometrics are available or not. */
+  export const enum BiometricStatusReason {
+    Available = 'Available',
+    /** The device is in use, but can prompt once it's released. */
+    DeviceBusy = 'DeviceBusy',
+    DeviceNotPresent = 'DeviceNotPresent',
+    /** The device is present, but the user hasn't set it up. */
+    NotConfigured = 'NotConfigured',
+    DisabledByPolicy = 'DisabledByPolicy',
+    /** The platform has no biometrics support. */
+    Unsupported = 'Unsupported'
+  }
+  export interface BiometricStatus {
+    available: boolean
+    reason: BiometricStatusReason
+  }
 }
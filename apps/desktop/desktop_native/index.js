@@ -206,7 +206,8 @@ if (!nativeBinding) {
   throw new Error(`Failed to load native binding`)
 }
 
-const { passwords, biometrics } = nativeBinding
+const { passwords, crypto, biometrics } = nativeBinding
 
 module.exports.passwords = passwords
+module.exports.crypto = crypto
 module.exports.biometrics = biometrics
@@ -0,0 +1,170 @@
+//! D-Bus helpers shared by the Linux biometric backends.
+
+use anyhow::Result;
+use gio::{
+    glib::{self, Variant},
+    Cancellable, DBusCallFlags, DBusConnection,
+};
+
+/// Errors meaning that the service isn't running, and can't be started.
+pub const SERVICE_UNAVAILABLE_ERRORS: &[&str] = &[
+    "org.freedesktop.DBus.Error.ServiceUnknown",
+    "org.freedesktop.DBus.Error.NameHasNoOwner",
+];
+
+/// Connects to the system bus, where fprintd and polkit run.
+pub fn system_bus() -> Result<DBusConnection> {
+    Ok(gio::bus_get_sync(gio::BusType::System, Cancellable::NONE)?)
+}
+
+/// Calls a method and waits for its reply. A `timeout_msec` of -1 uses the default of 25 seconds,
+/// and `i32::MAX` waits indefinitely.
+pub fn call(
+    connection: &DBusConnection,
+    bus_name: &str,
+    object_path: &str,
+    interface_name: &str,
+    method_name: &str,
+    parameters: Option<&Variant>,
+    timeout_msec: i32,
+) -> Result<Variant> {
+    let reply = connection.call_sync(
+        Some(bus_name),
+        object_path,
+        interface_name,
+        method_name,
+        parameters,
+        None,
+        DBusCallFlags::NONE,
+        timeout_msec,
+        Cancellable::NONE,
+    )?;
+    Ok(reply)
+}
+
+/// Whether the remote peer returned one of the named D-Bus errors.
+pub fn is_remote_error(error: &anyhow::Error, names: &[&str]) -> bool {
+    match error.downcast_ref::<glib::Error>() {
+        Some(error) => remote_error_name(error).is_some_and(|name| names.contains(&name)),
+        None => false,
+    }
+}
+
+/// Whether the call timed out waiting for the reply.
+pub fn is_timeout(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<glib::Error>() {
+        Some(error) => error.matches(gio::IOErrorEnum::TimedOut),
+        None => false,
+    }
+}
+
+// GDBus prefixes the messages of errors returned by the remote peer with
+// `GDBus.Error:<error name>: `
+fn remote_error_name(error: &glib::Error) -> Option<&str> {
+    let (name, _) = error
+        .message()
+        .strip_prefix("GDBus.Error:")?
+        .split_once(':')?;
+    Some(name)
+}
+
+#[cfg(test)]
+pub mod test_util {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        sync::mpsc,
+        thread,
+    };
+
+    use gio::{
+        glib::{MainContext, MainLoop},
+        BusNameOwnerFlags, Cancellable, DBusConnection, DBusConnectionFlags,
+    };
+
+    /// A private bus run by its own `dbus-daemon`, for mock services to serve on.
+    pub struct PrivateBus {
+        daemon: Child,
+        address: String,
+        services: Vec<MainLoop>,
+    }
+
+    impl PrivateBus {
+        pub fn start() -> Self {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .expect("dbus-daemon is required to run these tests");
+
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+
+            Self {
+                daemon,
+                address: address.trim().to_owned(),
+                services: Vec::new(),
+            }
+        }
+
+        pub fn connect(&self) -> DBusConnection {
+            DBusConnection::for_address_sync(
+                &self.address,
+                DBusConnectionFlags::AUTHENTICATION_CLIENT
+                    | DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+                None,
+                Cancellable::NONE,
+            )
+            .unwrap()
+        }
+
+        /// Serves a mock service under `name` on a thread of its own. `register` exports its
+        /// objects, and this returns once the name is owned.
+        pub fn serve(
+            &mut self,
+            name: &'static str,
+            register: impl FnOnce(&DBusConnection) + Send + 'static,
+        ) {
+            let connection = self.connect();
+            let (started, running) = mpsc::channel();
+
+            thread::spawn(move || {
+                let context = MainContext::new();
+                let main_loop = MainLoop::new(Some(&context), false);
+
+                context
+                    .with_thread_default(|| {
+                        register(&connection);
+
+                        let acquired = main_loop.clone();
+                        gio::bus_own_name_on_connection(
+                            &connection,
+                            name,
+                            BusNameOwnerFlags::NONE,
+                            move |_, _| {
+                                let _ = started.send(acquired.clone());
+                            },
+                            |_, _| {},
+                        );
+
+                        main_loop.run();
+                    })
+                    .unwrap();
+            });
+
+            self.services.push(running.recv().unwrap());
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            for main_loop in &self.services {
+                main_loop.quit();
+            }
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+}
@@ -0,0 +1,429 @@
+//! Fingerprint verification with fprintd, which serves `net.reactivated.Fprint` on the system bus.
+
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use gio::{
+    glib::{variant::ObjectPath, MainContext, ToVariant, Variant},
+    DBusConnection, DBusSignalFlags,
+};
+
+use super::dbus;
+use crate::biometrics::PromptOutcome;
+
+const BUS_NAME: &str = "net.reactivated.Fprint";
+const MANAGER_PATH: &str = "/net/reactivated/Fprint/Manager";
+const MANAGER_INTERFACE: &str = "net.reactivated.Fprint.Manager";
+const DEVICE_INTERFACE: &str = "net.reactivated.Fprint.Device";
+
+// Errors meaning there is nothing to verify with, rather than that fprintd failed
+const UNAVAILABLE_ERRORS: &[&str] = &[
+    "net.reactivated.Fprint.Error.NoSuchDevice",
+    "net.reactivated.Fprint.Error.NoEnrolledPrints",
+];
+
+/// Returned by `Claim` while another client has claimed the device.
+const IN_USE_ERROR: &str = "net.reactivated.Fprint.Error.AlreadyInUse";
+
+/// How long to wait for a finger on the reader before giving up.
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(60);
+
+// How often to dispatch signals while waiting for a result
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The `result` of a `VerifyStatus` signal.
+#[derive(Debug, PartialEq)]
+enum VerifyStatus {
+    Match,
+    NoMatch,
+    /// The scan was unusable, and the user should try again.
+    Retry,
+    /// The device failed, e.g. `verify-disconnected` or `verify-unknown-error`.
+    Failed(String),
+}
+
+impl VerifyStatus {
+    fn parse(result: &str) -> Self {
+        match result {
+            "verify-match" => VerifyStatus::Match,
+            "verify-no-match" => VerifyStatus::NoMatch,
+            "verify-retry-scan"
+            | "verify-swipe-too-short"
+            | "verify-finger-not-centered"
+            | "verify-remove-and-retry" => VerifyStatus::Retry,
+            result => VerifyStatus::Failed(result.to_owned()),
+        }
+    }
+}
+
+pub struct Fprint {
+    connection: DBusConnection,
+}
+
+impl Fprint {
+    pub fn new(connection: DBusConnection) -> Self {
+        Self { connection }
+    }
+
+    /// Whether there is a fingerprint reader with prints enrolled for the current user.
+    pub fn available(&self) -> Result<bool> {
+        let enrolled = self.default_device().and_then(|device| {
+            let reply = self.call(
+                &device,
+                DEVICE_INTERFACE,
+                "ListEnrolledFingers",
+                Some(&("",).to_variant()),
+            )?;
+            reply
+                .get::<(Vec<String>,)>()
+                .ok_or_else(|| unexpected_reply(&reply))
+        });
+
+        match enrolled {
+            Ok((fingers,)) => Ok(!fingers.is_empty()),
+            Err(e)
+                if dbus::is_remote_error(&e, UNAVAILABLE_ERRORS)
+                    || dbus::is_remote_error(&e, dbus::SERVICE_UNAVAILABLE_ERRORS) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Claims the default reader for the current user and verifies any of their enrolled fingers.
+    ///
+    /// No finger scanned in time counts as the user canceling.
+    pub fn verify(&self) -> Result<PromptOutcome> {
+        let device = self.default_device()?;
+
+        let claimed = self.call(
+            &device,
+            DEVICE_INTERFACE,
+            "Claim",
+            Some(&("",).to_variant()),
+        );
+        match claimed {
+            Err(e) if dbus::is_remote_error(&e, &[IN_USE_ERROR]) => {
+                return Ok(PromptOutcome::DeviceBusy)
+            }
+            claimed => claimed?,
+        };
+        scopeguard::defer!({
+            let _ = self.call(&device, DEVICE_INTERFACE, "Release", None);
+        });
+
+        // Signal callbacks run on the thread default main context of the subscribing thread, so
+        // give this call its own to iterate
+        let context = MainContext::new();
+        context.with_thread_default(|| self.wait_for_verification(&context, &device))?
+    }
+
+    fn wait_for_verification(&self, context: &MainContext, device: &str) -> Result<PromptOutcome> {
+        let (sender, statuses) = mpsc::channel();
+        let subscription = self.connection.signal_subscribe(
+            Some(BUS_NAME),
+            Some(DEVICE_INTERFACE),
+            Some("VerifyStatus"),
+            Some(device),
+            None,
+            DBusSignalFlags::NONE,
+            move |_, _, _, _, _, parameters| {
+                if let Some(status) = parameters.get::<(String, bool)>() {
+                    let _ = sender.send(status);
+                }
+            },
+        );
+        scopeguard::defer!(self.connection.signal_unsubscribe(subscription));
+
+        self.call(
+            device,
+            DEVICE_INTERFACE,
+            "VerifyStart",
+            Some(&("any",).to_variant()),
+        )?;
+        scopeguard::defer!({
+            let _ = self.call(device, DEVICE_INTERFACE, "VerifyStop", None);
+        });
+
+        let deadline = Instant::now() + VERIFY_TIMEOUT;
+        loop {
+            while context.iteration(false) {}
+
+            let (result, done) = match statuses.recv_timeout(POLL_INTERVAL) {
+                Ok(status) => status,
+                Err(RecvTimeoutError::Timeout) if Instant::now() < deadline => continue,
+                Err(RecvTimeoutError::Timeout) => return Ok(PromptOutcome::Canceled),
+                Err(RecvTimeoutError::Disconnected) => bail!("fprintd stopped sending results"),
+            };
+
+            match VerifyStatus::parse(&result) {
+                VerifyStatus::Match => return Ok(PromptOutcome::Verified),
+                VerifyStatus::Failed(result) => {
+                    bail!("fingerprint verification failed: {}", result)
+                }
+                // fprintd keeps scanning until it reports that it's done
+                VerifyStatus::NoMatch | VerifyStatus::Retry if done => {
+                    return Ok(PromptOutcome::RetriesExhausted)
+                }
+                VerifyStatus::NoMatch | VerifyStatus::Retry => continue,
+            }
+        }
+    }
+
+    fn default_device(&self) -> Result<String> {
+        let reply = self.call(MANAGER_PATH, MANAGER_INTERFACE, "GetDefaultDevice", None)?;
+        let (device,) = reply
+            .get::<(ObjectPath,)>()
+            .ok_or_else(|| unexpected_reply(&reply))?;
+        Ok(device.as_str().to_owned())
+    }
+
+    fn call(
+        &self,
+        object_path: &str,
+        interface_name: &str,
+        method_name: &str,
+        parameters: Option<&Variant>,
+    ) -> Result<Variant> {
+        dbus::call(
+            &self.connection,
+            BUS_NAME,
+            object_path,
+            interface_name,
+            method_name,
+            parameters,
+            -1,
+        )
+    }
+}
+
+fn unexpected_reply(reply: &Variant) -> anyhow::Error {
+    anyhow!("unexpected reply from fprintd: {}", reply.type_())
+}
+
+#[cfg(test)]
+pub mod test_util {
+    use gio::DBusNodeInfo;
+
+    use super::*;
+    use crate::biometric::dbus::test_util::PrivateBus;
+
+    const DEVICE_PATH: &str = "/net/reactivated/Fprint/Device/0";
+
+    const INTROSPECTION: &str = r#"
+        <node>
+          <interface name="net.reactivated.Fprint.Manager">
+            <method name="GetDefaultDevice">
+              <arg type="o" name="device" direction="out"/>
+            </method>
+          </interface>
+          <interface name="net.reactivated.Fprint.Device">
+            <method name="ListEnrolledFingers">
+              <arg type="s" name="username" direction="in"/>
+              <arg type="as" name="enrolled_fingers" direction="out"/>
+            </method>
+            <method name="Claim">
+              <arg type="s" name="username" direction="in"/>
+            </method>
+            <method name="Release"/>
+            <method name="VerifyStart">
+              <arg type="s" name="finger_name" direction="in"/>
+            </method>
+            <method name="VerifyStop"/>
+            <signal name="VerifyStatus">
+              <arg type="s" name="result"/>
+              <arg type="b" name="done"/>
+            </signal>
+          </interface>
+        </node>
+    "#;
+
+    /// The reader the mock fprintd reports, if any.
+    #[derive(Clone)]
+    pub struct MockDevice {
+        pub enrolled: Vec<&'static str>,
+        /// Whether another client has claimed the device.
+        pub in_use: bool,
+        /// The `VerifyStatus` signals to emit after `VerifyStart`.
+        pub statuses: Vec<(&'static str, bool)>,
+    }
+
+    /// Serves a mock fprintd on `bus`, with `device` as its default device.
+    pub fn serve(bus: &mut PrivateBus, device: Option<MockDevice>) {
+        bus.serve(BUS_NAME, move |connection| {
+            let node = DBusNodeInfo::for_xml(INTROSPECTION).unwrap();
+            let manager = node.lookup_interface(MANAGER_INTERFACE).unwrap();
+            let device_interface = node.lookup_interface(DEVICE_INTERFACE).unwrap();
+
+            let has_device = device.is_some();
+            connection
+                .register_object(
+                    MANAGER_PATH,
+                    &manager,
+                    move |_, _, _, _, _, _, invocation| match has_device {
+                        true => invocation.return_value(Some(
+                            &(ObjectPath::try_from(DEVICE_PATH).unwrap(),).to_variant(),
+                        )),
+                        false => invocation.return_dbus_error(
+                            "net.reactivated.Fprint.Error.NoSuchDevice",
+                            "No devices available",
+                        ),
+                    },
+                    |_, _, _, _, _| unreachable!(),
+                    |_, _, _, _, _, _| false,
+                )
+                .unwrap();
+
+            if let Some(device) = device {
+                connection
+                    .register_object(
+                        DEVICE_PATH,
+                        &device_interface,
+                        move |connection, _, _, _, method, _, invocation| match method {
+                            "ListEnrolledFingers" if device.enrolled.is_empty() => invocation
+                                .return_dbus_error(
+                                    "net.reactivated.Fprint.Error.NoEnrolledPrints",
+                                    "No fingerprints enrolled",
+                                ),
+                            "ListEnrolledFingers" => {
+                                let enrolled = device
+                                    .enrolled
+                                    .iter()
+                                    .map(|finger| finger.to_string())
+                                    .collect::<Vec<_>>();
+                                invocation.return_value(Some(&(enrolled,).to_variant()))
+                            }
+                            "Claim" if device.in_use => invocation
+                                .return_dbus_error(IN_USE_ERROR, "Device was already claimed"),
+                            "VerifyStart" => {
+                                invocation.return_value(None);
+                                for (result, done) in &device.statuses {
+                                    connection
+                                        .emit_signal(
+                                            None,
+                                            DEVICE_PATH,
+                                            DEVICE_INTERFACE,
+                                            "VerifyStatus",
+                                            Some(&(*result, *done).to_variant()),
+                                        )
+                                        .unwrap();
+                                }
+                            }
+                            _ => invocation.return_value(None),
+                        },
+                        |_, _, _, _, _| unreachable!(),
+                        |_, _, _, _, _, _| false,
+                    )
+                    .unwrap();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{test_util::*, *};
+    use crate::biometric::dbus::test_util::PrivateBus;
+
+    fn with_fprintd(device: Option<MockDevice>) -> PrivateBus {
+        let mut bus = PrivateBus::start();
+        serve(&mut bus, device);
+        bus
+    }
+
+    fn fprint(bus: &PrivateBus) -> Fprint {
+        Fprint::new(bus.connect())
+    }
+
+    fn reader(statuses: Vec<(&'static str, bool)>) -> Option<MockDevice> {
+        Some(MockDevice {
+            enrolled: vec!["right-index-finger"],
+            in_use: false,
+            statuses,
+        })
+    }
+
+    #[test]
+    fn test_parse_status() {
+        assert_eq!(VerifyStatus::parse("verify-match"), VerifyStatus::Match);
+        assert_eq!(
+            VerifyStatus::parse("verify-no-match"),
+            VerifyStatus::NoMatch
+        );
+        assert_eq!(
+            VerifyStatus::parse("verify-swipe-too-short"),
+            VerifyStatus::Retry
+        );
+        assert_eq!(
+            VerifyStatus::parse("verify-disconnected"),
+            VerifyStatus::Failed("verify-disconnected".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_verify_match() {
+        let bus = with_fprintd(reader(vec![
+            ("verify-retry-scan", false),
+            ("verify-match", true),
+        ]));
+        assert_eq!(fprint(&bus).verify().unwrap(), PromptOutcome::Verified);
+    }
+
+    #[test]
+    fn test_verify_no_match() {
+        let bus = with_fprintd(reader(vec![
+            ("verify-no-match", false),
+            ("verify-no-match", true),
+        ]));
+        assert_eq!(
+            fprint(&bus).verify().unwrap(),
+            PromptOutcome::RetriesExhausted
+        );
+    }
+
+    #[test]
+    fn test_verify_disconnected() {
+        let bus = with_fprintd(reader(vec![("verify-disconnected", true)]));
+        assert!(fprint(&bus).verify().is_err());
+    }
+
+    #[test]
+    fn test_verify_in_use() {
+        let bus = with_fprintd(Some(MockDevice {
+            enrolled: vec!["right-index-finger"],
+            in_use: true,
+            statuses: vec![("verify-match", true)],
+        }));
+        assert_eq!(fprint(&bus).verify().unwrap(), PromptOutcome::DeviceBusy);
+    }
+
+    #[test]
+    fn test_verify_without_device() {
+        let bus = with_fprintd(None);
+        assert!(fprint(&bus).verify().is_err());
+    }
+
+    #[test]
+    fn test_available() {
+        let bus = with_fprintd(reader(vec![]));
+        assert!(fprint(&bus).available().unwrap());
+
+        let bus = with_fprintd(Some(MockDevice {
+            enrolled: vec![],
+            in_use: false,
+            statuses: vec![],
+        }));
+        assert!(!fprint(&bus).available().unwrap());
+
+        let bus = with_fprintd(None);
+        assert!(!fprint(&bus).available().unwrap());
+
+        // fprintd isn't running at all
+        let bus = PrivateBus::start();
+        assert!(!fprint(&bus).available().unwrap());
+    }
+}
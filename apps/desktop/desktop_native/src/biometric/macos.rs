@@ -1,17 +1,20 @@
 use anyhow::{bail, Result};
 
-use crate::biometrics::{KeyMaterial, OsDerivedKey};
+use crate::biometrics::{
+    BiometricSecretOptions, BiometricStatus, BiometricStatusReason, KeyMaterial, OsDerivedKey,
+    PromptOutcome,
+};
 
 /// The MacOS implementation of the biometric trait.
 pub struct Biometric {}
 
 impl super::BiometricTrait for Biometric {
-    fn prompt(_hwnd: Vec<u8>, _message: String) -> Result<bool> {
-        bail!("platform not supported");
+    fn prompt(_hwnd: Vec<u8>, _message: String) -> Result<PromptOutcome> {
+        Ok(PromptOutcome::Unavailable)
     }
 
-    fn available() -> Result<bool> {
-        bail!("platform not supported");
+    fn available() -> Result<BiometricStatus> {
+        Ok(BiometricStatusReason::Unsupported.into())
     }
 
     fn derive_key_material(_iv_str: Option<&str>) -> Result<OsDerivedKey> {
@@ -22,6 +25,7 @@ impl super::BiometricTrait for Biometric {
         _service: &str,
         _account: &str,
         _key_material: Option<KeyMaterial>,
+        _options: Option<BiometricSecretOptions>,
     ) -> Result<String> {
         bail!("platform not supported");
     }
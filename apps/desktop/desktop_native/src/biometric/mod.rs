@@ -5,13 +5,25 @@ use anyhow::Result;
 #[cfg_attr(target_os = "macos", path = "macos.rs")]
 mod biometric;
 
+#[cfg(target_os = "linux")]
+mod dbus;
+#[cfg(target_os = "linux")]
+mod fprint;
+#[cfg(target_os = "linux")]
+mod polkit;
+// Only the Windows backend protects secrets with key material so far
+#[cfg_attr(not(windows), allow(dead_code))]
+mod protected_secret;
+
 pub use biometric::Biometric;
 
-use crate::biometrics::{KeyMaterial, OsDerivedKey};
+use crate::biometrics::{
+    BiometricSecretOptions, BiometricStatus, KeyMaterial, OsDerivedKey, PromptOutcome,
+};
 
 pub trait BiometricTrait {
-    fn prompt(hwnd: Vec<u8>, message: String) -> Result<bool>;
-    fn available() -> Result<bool>;
+    fn prompt(hwnd: Vec<u8>, message: String) -> Result<PromptOutcome>;
+    fn available() -> Result<BiometricStatus>;
     fn derive_key_material(secret: Option<&str>) -> Result<OsDerivedKey>;
     fn set_biometric_secret(
         service: &str,
@@ -24,5 +36,6 @@ pub trait BiometricTrait {
         service: &str,
         account: &str,
         key_material: Option<KeyMaterial>,
+        options: Option<BiometricSecretOptions>,
     ) -> Result<String>;
 }
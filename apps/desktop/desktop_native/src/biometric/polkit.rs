@@ -0,0 +1,382 @@
+//! User presence verification with polkit, which serves `org.freedesktop.PolicyKit1` on the system
+//! bus and has the user authenticate through the authentication agent of their session.
+
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use gio::{
+    glib::{ToVariant, Variant},
+    DBusConnection,
+};
+
+use super::dbus;
+use crate::biometrics::PromptOutcome;
+
+const BUS_NAME: &str = "org.freedesktop.PolicyKit1";
+const AUTHORITY_PATH: &str = "/org/freedesktop/PolicyKit1/Authority";
+const AUTHORITY_INTERFACE: &str = "org.freedesktop.PolicyKit1.Authority";
+
+/// The action defined by `com.bitwarden.desktop.policy`, which is installed with the desktop app.
+pub const ACTION_ID: &str = "com.bitwarden.desktop.unlock";
+
+// `CheckAuthorizationFlags.AllowUserInteraction`
+const ALLOW_USER_INTERACTION: u32 = 1;
+
+// How long the user has to respond to the agent, after which the check is cancelled
+const AUTHENTICATION_TIMEOUT_MSEC: i32 = 5 * 60 * 1000;
+
+// Identifies a check to `CancelCheckAuthorization`, unique among the checks of this process
+static NEXT_CANCELLATION_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A subject as polkit passes it over D-Bus: its kind, and the details identifying it.
+type Subject = (String, HashMap<String, Variant>);
+
+/// The reply to `CheckAuthorization`: whether the subject is authorized, whether it could be by
+/// authenticating, and details such as `polkit.dismissed`.
+type AuthorizationResult = (bool, bool, HashMap<String, String>);
+
+/// An element of the `EnumerateActions` reply, starting with the action ID.
+type ActionDescription = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    u32,
+    u32,
+    u32,
+    HashMap<String, String>,
+);
+
+pub struct Polkit {
+    connection: DBusConnection,
+}
+
+impl Polkit {
+    pub fn new(connection: DBusConnection) -> Self {
+        Self { connection }
+    }
+
+    /// Whether the Bitwarden action is installed, and polkit would have the user authenticate to
+    /// authorize it for this connection. The action's defaults only allow that in the active
+    /// session of the user, which polkit looks up from the connection itself.
+    ///
+    /// polkit can't report whether that session runs an authentication agent. Without one,
+    /// `check_authorization` reports the prompt as unavailable.
+    pub fn available(&self) -> Result<bool> {
+        let available = self
+            .action_registered()
+            .and_then(|registered| Ok(registered && self.can_authenticate()?));
+
+        match available {
+            Err(e) if dbus::is_remote_error(&e, dbus::SERVICE_UNAVAILABLE_ERRORS) => Ok(false),
+            available => available,
+        }
+    }
+
+    /// Asks polkit to authorize the Bitwarden action for this connection, which has the agent
+    /// authenticate the user with `message`. A user who doesn't respond in time isn't authorized.
+    pub fn check_authorization(&self, message: &str) -> Result<PromptOutcome> {
+        let details = HashMap::from([("polkit.message".to_owned(), message.to_owned())]);
+        let cancellation_id = format!(
+            "bitwarden-{}",
+            NEXT_CANCELLATION_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        let result = self.check(
+            details,
+            ALLOW_USER_INTERACTION,
+            &cancellation_id,
+            AUTHENTICATION_TIMEOUT_MSEC,
+        );
+        let (authorized, challenge, details) = match result {
+            Ok(result) => result,
+            Err(e) if dbus::is_timeout(&e) => {
+                // Closes the dialog of the agent
+                self.call(
+                    "CancelCheckAuthorization",
+                    Some(&(&cancellation_id,).to_variant()),
+                    -1,
+                )?;
+                return Ok(PromptOutcome::Canceled);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let dismissed = details.get("polkit.dismissed").map(String::as_str) == Some("true");
+        Ok(match (authorized, challenge, dismissed) {
+            (true, _, _) => PromptOutcome::Verified,
+            // Still a challenge although the user could be asked, so no agent was there to ask them
+            (false, true, _) => PromptOutcome::Unavailable,
+            (false, false, true) => PromptOutcome::Canceled,
+            // The agent gives up after the user failed to authenticate a few times
+            (false, false, false) => PromptOutcome::RetriesExhausted,
+        })
+    }
+
+    // Checks the authorization without asking the user, which polkit answers with a challenge when
+    // the user could authenticate
+    fn can_authenticate(&self) -> Result<bool> {
+        let (authorized, challenge, _) = self.check(HashMap::new(), 0, "", -1)?;
+        Ok(authorized || challenge)
+    }
+
+    fn check(
+        &self,
+        details: HashMap<String, String>,
+        flags: u32,
+        cancellation_id: &str,
+        timeout_msec: i32,
+    ) -> Result<AuthorizationResult> {
+        let parameters = (
+            bus_name_subject(&self.connection)?,
+            ACTION_ID,
+            details,
+            flags,
+            cancellation_id,
+        );
+        let reply = self.call(
+            "CheckAuthorization",
+            Some(&parameters.to_variant()),
+            timeout_msec,
+        )?;
+
+        let (result,) = reply
+            .get::<(AuthorizationResult,)>()
+            .ok_or_else(|| unexpected_reply(&reply))?;
+        Ok(result)
+    }
+
+    fn action_registered(&self) -> Result<bool> {
+        let reply = self.call("EnumerateActions", Some(&("",).to_variant()), -1)?;
+        let (actions,) = reply
+            .get::<(Vec<ActionDescription>,)>()
+            .ok_or_else(|| unexpected_reply(&reply))?;
+        Ok(actions.iter().any(|action| action.0 == ACTION_ID))
+    }
+
+    fn call(
+        &self,
+        method_name: &str,
+        parameters: Option<&Variant>,
+        timeout_msec: i32,
+    ) -> Result<Variant> {
+        dbus::call(
+            &self.connection,
+            BUS_NAME,
+            AUTHORITY_PATH,
+            AUTHORITY_INTERFACE,
+            method_name,
+            parameters,
+            timeout_msec,
+        )
+    }
+}
+
+// The connection as polkit sees it, which unlike a process ID can't be reused by another process
+fn bus_name_subject(connection: &DBusConnection) -> Result<Subject> {
+    let name = connection
+        .unique_name()
+        .ok_or_else(|| anyhow!("the connection to polkit has no unique name"))?;
+    let details = HashMap::from([("name".to_owned(), name.as_str().to_variant())]);
+    Ok(("system-bus-name".to_owned(), details))
+}
+
+fn unexpected_reply(reply: &Variant) -> anyhow::Error {
+    anyhow!("unexpected reply from polkit: {}", reply.type_())
+}
+
+#[cfg(test)]
+pub mod test_util {
+    use gio::DBusNodeInfo;
+
+    use super::*;
+    use crate::biometric::dbus::test_util::PrivateBus;
+
+    const INTROSPECTION: &str = r#"
+        <node>
+          <interface name="org.freedesktop.PolicyKit1.Authority">
+            <method name="EnumerateActions">
+              <arg type="s" name="locale" direction="in"/>
+              <arg type="a(ssssssuuua{ss})" name="action_descriptions" direction="out"/>
+            </method>
+            <method name="CheckAuthorization">
+              <arg type="(sa{sv})" name="subject" direction="in"/>
+              <arg type="s" name="action_id" direction="in"/>
+              <arg type="a{ss}" name="details" direction="in"/>
+              <arg type="u" name="flags" direction="in"/>
+              <arg type="s" name="cancellation_id" direction="in"/>
+              <arg type="(bba{ss})" name="result" direction="out"/>
+            </method>
+            <method name="CancelCheckAuthorization">
+              <arg type="s" name="cancellation_id" direction="in"/>
+            </method>
+          </interface>
+        </node>
+    "#;
+
+    /// The message that the mock agent expects to show.
+    pub const MESSAGE: &str = "Verify for Bitwarden";
+
+    /// The parameters of `CheckAuthorization`.
+    type CheckParameters = (Subject, String, HashMap<String, String>, u32, String);
+
+    /// How the mock polkit authority behaves.
+    pub struct MockAuthority {
+        pub action_installed: bool,
+        /// Whether the caller is in the active session, where the user can authenticate.
+        pub in_session: bool,
+        /// How the user responds to the agent of the session, if there is one.
+        pub agent: Option<Response>,
+    }
+
+    #[derive(Clone, Copy)]
+    pub enum Response {
+        Authenticate,
+        Dismiss,
+        Fail,
+    }
+
+    /// Serves a mock polkit authority on `bus`.
+    pub fn serve(bus: &mut PrivateBus, authority: MockAuthority) {
+        bus.serve(BUS_NAME, move |connection| {
+            let node = DBusNodeInfo::for_xml(INTROSPECTION).unwrap();
+            let interface = node.lookup_interface(AUTHORITY_INTERFACE).unwrap();
+
+            connection
+                .register_object(
+                    AUTHORITY_PATH,
+                    &interface,
+                    move |_, sender, _, _, method, parameters, invocation| match method {
+                        "EnumerateActions" => {
+                            let mut actions: Vec<ActionDescription> = Vec::new();
+                            if authority.action_installed {
+                                actions.push((
+                                    ACTION_ID.to_owned(),
+                                    "Unlock Bitwarden".to_owned(),
+                                    String::new(),
+                                    String::new(),
+                                    String::new(),
+                                    String::new(),
+                                    0,
+                                    0,
+                                    2,
+                                    HashMap::new(),
+                                ));
+                            }
+                            invocation.return_value(Some(&(actions,).to_variant()))
+                        }
+                        "CheckAuthorization" => {
+                            let ((kind, subject), action_id, details, flags, cancellation_id) =
+                                parameters.get::<CheckParameters>().unwrap();
+                            let name = subject.get("name").and_then(|name| name.get::<String>());
+                            let can_authenticate = authority.in_session
+                                && kind == "system-bus-name"
+                                && name.as_deref() == Some(sender)
+                                && action_id == ACTION_ID;
+                            let prompts = flags == ALLOW_USER_INTERACTION
+                                && !cancellation_id.is_empty()
+                                && details.get("polkit.message").map(String::as_str)
+                                    == Some(MESSAGE);
+
+                            let result: AuthorizationResult = match authority.agent {
+                                _ if !can_authenticate => (false, false, HashMap::new()),
+                                None => (false, true, HashMap::new()),
+                                Some(_) if !prompts => (false, true, HashMap::new()),
+                                Some(Response::Authenticate) => (true, false, HashMap::new()),
+                                Some(Response::Dismiss) => (
+                                    false,
+                                    false,
+                                    HashMap::from([(
+                                        "polkit.dismissed".to_owned(),
+                                        "true".to_owned(),
+                                    )]),
+                                ),
+                                Some(Response::Fail) => (false, false, HashMap::new()),
+                            };
+                            invocation.return_value(Some(&(result,).to_variant()))
+                        }
+                        _ => invocation.return_value(None),
+                    },
+                    |_, _, _, _, _| unreachable!(),
+                    |_, _, _, _, _, _| false,
+                )
+                .unwrap();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{test_util::*, *};
+    use crate::biometric::dbus::test_util::PrivateBus;
+
+    fn with_polkit(authority: MockAuthority) -> PrivateBus {
+        let mut bus = PrivateBus::start();
+        serve(&mut bus, authority);
+        bus
+    }
+
+    fn polkit(bus: &PrivateBus) -> Polkit {
+        Polkit::new(bus.connect())
+    }
+
+    fn authority(agent: Option<Response>) -> MockAuthority {
+        MockAuthority {
+            action_installed: true,
+            in_session: true,
+            agent,
+        }
+    }
+
+    #[test]
+    fn test_check_authorization() {
+        let outcome = |agent| {
+            let bus = with_polkit(authority(agent));
+            polkit(&bus).check_authorization(MESSAGE).unwrap()
+        };
+
+        assert_eq!(
+            outcome(Some(Response::Authenticate)),
+            PromptOutcome::Verified
+        );
+        assert_eq!(outcome(Some(Response::Dismiss)), PromptOutcome::Canceled);
+        assert_eq!(
+            outcome(Some(Response::Fail)),
+            PromptOutcome::RetriesExhausted
+        );
+        assert_eq!(outcome(None), PromptOutcome::Unavailable);
+    }
+
+    #[test]
+    fn test_available() {
+        let bus = with_polkit(authority(Some(Response::Authenticate)));
+        assert!(polkit(&bus).available().unwrap());
+
+        // Whether there is an agent only shows once the user is prompted
+        let bus = with_polkit(authority(None));
+        assert!(polkit(&bus).available().unwrap());
+
+        let bus = with_polkit(MockAuthority {
+            action_installed: false,
+            ..authority(Some(Response::Authenticate))
+        });
+        assert!(!polkit(&bus).available().unwrap());
+
+        // polkit isn't running at all
+        let bus = PrivateBus::start();
+        assert!(!polkit(&bus).available().unwrap());
+    }
+
+    #[test]
+    fn test_available_without_session() {
+        let bus = with_polkit(MockAuthority {
+            in_session: false,
+            ..authority(Some(Response::Authenticate))
+        });
+        assert!(!polkit(&bus).available().unwrap());
+    }
+}
@@ -0,0 +1,441 @@
+//! Biometric secrets protected with key material, part of which only the OS hands out once the
+//! user is verified. The OS backends derive their key part, and this module turns it into the keys
+//! that encrypt and authenticate the secret stored in the keychain.
+//!
+//! Secrets are stored as `AesCbc256_HmacSha256_B64` cipher strings, with encryption and MAC keys
+//! stretched from the key material. The IV is the challenge the OS key part was derived from, which
+//! the desktop app reads back from the cipher string to derive the key part again, so the version
+//! of the envelope is tagged inside the authenticated plaintext instead.
+//!
+//! Older versions stored `AesCbc256_B64` cipher strings keyed directly by the key material, or the
+//! plaintext secret. Both are upgraded when read.
+
+use std::str::FromStr;
+
+use aes::cipher::generic_array::GenericArray;
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD as base64_engine, Engine};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::{
+    biometrics::{BiometricSecretOptions, KeyMaterial},
+    crypto::{self, CipherString, SymmetricCryptoKey},
+    error::CryptoError,
+    password::CredentialStore,
+};
+
+/// Prefixes the plaintext of version 1 envelopes.
+const ENVELOPE_V1: &str = "bitwarden-biometric-v1:";
+
+/// Decodes the challenge the OS key part was derived from, or generates a new one.
+pub fn challenge(challenge_b64: Option<&str>) -> Result<[u8; 16]> {
+    match challenge_b64 {
+        Some(challenge_b64) => base64_engine
+            .decode(challenge_b64)?
+            .try_into()
+            .map_err(|e: Vec<_>| anyhow!("Expect length {}, got {}", 16, e.len())),
+        None => Ok(random_challenge()),
+    }
+}
+
+/// Encrypts the secret with the key material, and stores it in the keychain.
+pub fn set(
+    store: &dyn CredentialStore,
+    service: &str,
+    account: &str,
+    secret: &str,
+    key_material: &KeyMaterial,
+    iv_b64: &str,
+) -> Result<String> {
+    let encrypted_secret = encrypt(secret, key_material, decode_iv(iv_b64)?)?;
+    store.set_password(service, account, &encrypted_secret)?;
+    Ok(encrypted_secret)
+}
+
+/// Reads the secret from the keychain, and decrypts it with the key material. Secrets stored by
+/// older versions are upgraded to the current envelope.
+pub fn get(
+    store: &dyn CredentialStore,
+    service: &str,
+    account: &str,
+    key_material: &KeyMaterial,
+    options: &BiometricSecretOptions,
+) -> Result<String> {
+    let stored = store.get_password(service, account)?;
+
+    let (secret, iv) = match CipherString::from_str(&stored) {
+        Ok(CipherString::AesCbc256_HmacSha256_B64 { iv, mac, data }) => {
+            return decrypt(&iv, &mac, &data, key_material);
+        }
+        Ok(CipherString::AesCbc256_B64 { iv, data }) => {
+            (decrypt_legacy(&iv, &data, key_material)?, iv)
+        }
+        Ok(_) => return Err(CryptoError::InvalidEncType.into()),
+        Err(_) if options.strict.unwrap_or(false) => {
+            bail!("The biometric secret is stored unencrypted")
+        }
+        Err(_) => match options.iv_b64.as_deref() {
+            Some(iv_b64) => (
+                Zeroizing::new(stored.as_str().to_owned()),
+                decode_iv(iv_b64)?,
+            ),
+            // Without the challenge, the secret can't be encrypted so that the OS key part can be
+            // derived again
+            None => return Ok(stored.as_str().to_owned()),
+        },
+    };
+
+    // Failing to upgrade doesn't affect this read, so a later read can try again
+    if let Ok(encrypted_secret) = encrypt(&secret, key_material, iv) {
+        let _ = store.set_password(service, account, &encrypted_secret);
+    }
+    Ok(secret.as_str().to_owned())
+}
+
+fn encrypt(secret: &str, key_material: &KeyMaterial, iv: [u8; 16]) -> Result<String> {
+    let key = envelope_key(key_material)?;
+    let mac_key = key.mac_key().ok_or(CryptoError::InvalidKey)?;
+
+    let plaintext = Zeroizing::new(format!("{}{}", ENVELOPE_V1, secret));
+    let encrypted = crypto::encrypt_aes256_hmac(plaintext.as_bytes(), iv, mac_key, key.key())?;
+
+    Ok(encrypted.to_string())
+}
+
+fn decrypt(
+    iv: &[u8; 16],
+    mac: &[u8; 32],
+    data: &Vec<u8>,
+    key_material: &KeyMaterial,
+) -> Result<String> {
+    let key = envelope_key(key_material)?;
+    let mac_key = key.mac_key().ok_or(CryptoError::InvalidKey)?;
+
+    // The MAC is verified before anything is decrypted
+    let decrypted = crypto::decrypt_aes256_hmac(iv, mac, data, mac_key, key.key())?;
+    let plaintext = Zeroizing::new(String::from_utf8(decrypted.to_vec())?);
+    match plaintext.strip_prefix(ENVELOPE_V1) {
+        Some(secret) => Ok(secret.to_owned()),
+        None => bail!("Unsupported biometric secret version"),
+    }
+}
+
+// Secrets stored before the envelope was versioned are keyed directly by the key material, and
+// aren't authenticated
+fn decrypt_legacy(
+    iv: &[u8; 16],
+    data: &Vec<u8>,
+    key_material: &KeyMaterial,
+) -> Result<Zeroizing<String>> {
+    let key = key_material.derive_key()?;
+    let decrypted = crypto::decrypt_aes256(iv, data, GenericArray::from_slice(key.as_slice()))?;
+
+    Ok(Zeroizing::new(String::from_utf8(decrypted.to_vec())?))
+}
+
+fn envelope_key(key_material: &KeyMaterial) -> Result<SymmetricCryptoKey> {
+    Ok(SymmetricCryptoKey::stretch(&*key_material.derive_key()?)?)
+}
+
+fn decode_iv(iv_b64: &str) -> Result<[u8; 16]> {
+    base64_engine
+        .decode(iv_b64)?
+        .try_into()
+        .map_err(|e: Vec<_>| anyhow!("Expected length {}, got {}", 16, e.len()))
+}
+
+fn random_challenge() -> [u8; 16] {
+    let mut challenge = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut challenge);
+    challenge
+}
+
+impl KeyMaterial {
+    fn digest_material(&self) -> Zeroizing<String> {
+        Zeroizing::new(match self.client_key_part_b64.as_deref() {
+            Some(client_key_part_b64) => {
+                format!("{}|{}", self.os_key_part_b64, client_key_part_b64)
+            }
+            None => self.os_key_part_b64.clone(),
+        })
+    }
+
+    pub fn derive_key(&self) -> Result<Zeroizing<[u8; 32]>> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        let mut hasher = Sha256::new();
+        hasher.update(self.digest_material().as_bytes());
+        hasher.finalize_into(GenericArray::from_mut_slice(key.as_mut_slice()));
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::password::MemoryStore;
+
+    const SERVICE: &str = "BitwardenTest";
+    const IV_B64: &str = "l9fhDUP/wDJcKwmEzcb/3w==";
+    // output from encrypting "secret" with the legacy envelope
+    const LEGACY_SECRET: &str = "0.l9fhDUP/wDJcKwmEzcb/3w==|uP4LcqoCCj5FxBDP77NV6Q==";
+
+    fn key_material() -> KeyMaterial {
+        KeyMaterial {
+            os_key_part_b64: "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned(),
+            client_key_part_b64: Some("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned()),
+        }
+    }
+
+    fn options(strict: bool, iv_b64: Option<&str>) -> BiometricSecretOptions {
+        BiometricSecretOptions {
+            strict: Some(strict),
+            iv_b64: iv_b64.map(str::to_owned),
+        }
+    }
+
+    fn stored_enc_type(store: &MemoryStore, account: &str) -> u8 {
+        let stored = store.get_password(SERVICE, account).unwrap();
+        CipherString::from_str(&stored).unwrap().enc_type()
+    }
+
+    #[test]
+    fn test_challenge() {
+        assert_eq!(
+            base64_engine.encode(challenge(Some(IV_B64)).unwrap()),
+            IV_B64
+        );
+        assert_ne!(challenge(None).unwrap(), challenge(None).unwrap());
+        assert!(challenge(Some("AAAA")).is_err());
+    }
+
+    #[test]
+    fn test_encrypt() {
+        let key_material = key_material();
+        let secret = encrypt("secret", &key_material, decode_iv(IV_B64).unwrap())
+            .unwrap()
+            .parse::<CipherString>()
+            .unwrap();
+
+        match secret {
+            CipherString::AesCbc256_HmacSha256_B64 { iv, mac, data } => {
+                // The desktop app derives the OS key part again from the IV
+                assert_eq!(IV_B64, base64_engine.encode(iv));
+                assert_eq!(decrypt(&iv, &mac, &data, &key_material).unwrap(), "secret");
+            }
+            _ => panic!("Invalid cipher string"),
+        }
+    }
+
+    #[test]
+    fn test_decrypt() {
+        let secret = CipherString::from_str(LEGACY_SECRET).unwrap();
+        let key_material = key_material();
+        match secret {
+            CipherString::AesCbc256_B64 { iv, data } => {
+                assert_eq!(
+                    *decrypt_legacy(&iv, &data, &key_material).unwrap(),
+                    "secret"
+                )
+            }
+            _ => panic!("Invalid cipher string"),
+        }
+    }
+
+    #[test]
+    fn test_decrypt_rejects_tampering() {
+        let key_material = key_material();
+        let encrypted = encrypt("secret", &key_material, decode_iv(IV_B64).unwrap()).unwrap();
+        let (iv, mac, mut data) = match CipherString::from_str(&encrypted).unwrap() {
+            CipherString::AesCbc256_HmacSha256_B64 { iv, mac, data } => (iv, mac, data),
+            _ => panic!("Invalid cipher string"),
+        };
+
+        data[0] ^= 1;
+        assert!(decrypt(&iv, &mac, &data, &key_material).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_rejects_unknown_version() {
+        let key_material = key_material();
+        let key = envelope_key(&key_material).unwrap();
+        let encrypted = crypto::encrypt_aes256_hmac(
+            b"bitwarden-biometric-v2:secret",
+            decode_iv(IV_B64).unwrap(),
+            key.mac_key().unwrap(),
+            key.key(),
+        )
+        .unwrap();
+
+        match encrypted {
+            CipherString::AesCbc256_HmacSha256_B64 { iv, mac, data } => {
+                assert!(decrypt(&iv, &mac, &data, &key_material).is_err());
+            }
+            _ => panic!("Invalid cipher string"),
+        }
+    }
+
+    #[test]
+    fn test_set_and_get() {
+        let account = "ProtectedSecretRoundTrip";
+        let store = MemoryStore::new();
+
+        set(&store, SERVICE, account, "secret", &key_material(), IV_B64).unwrap();
+        assert_eq!(stored_enc_type(&store, account), 2);
+        assert_eq!(
+            get(
+                &store,
+                SERVICE,
+                account,
+                &key_material(),
+                &options(true, None)
+            )
+            .unwrap(),
+            "secret"
+        );
+
+        // The wrong key material fails the MAC, rather than returning garbage
+        let mut wrong_key_material = key_material();
+        wrong_key_material.client_key_part_b64 = None;
+        assert!(get(
+            &store,
+            SERVICE,
+            account,
+            &wrong_key_material,
+            &options(false, None)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_get_upgrades_legacy_secret() {
+        let account = "ProtectedSecretLegacy";
+        let store = MemoryStore::new();
+        store.set_password(SERVICE, account, LEGACY_SECRET).unwrap();
+
+        assert_eq!(
+            get(
+                &store,
+                SERVICE,
+                account,
+                &key_material(),
+                &options(true, None)
+            )
+            .unwrap(),
+            "secret"
+        );
+        assert_eq!(stored_enc_type(&store, account), 2);
+        assert_eq!(
+            get(
+                &store,
+                SERVICE,
+                account,
+                &key_material(),
+                &options(true, None)
+            )
+            .unwrap(),
+            "secret"
+        );
+    }
+
+    #[test]
+    fn test_get_upgrades_plaintext_secret() {
+        let account = "ProtectedSecretPlaintext";
+        let store = MemoryStore::new();
+        store.set_password(SERVICE, account, "secret").unwrap();
+
+        // Without the challenge the secret is returned as is
+        assert_eq!(
+            get(
+                &store,
+                SERVICE,
+                account,
+                &key_material(),
+                &options(false, None)
+            )
+            .unwrap(),
+            "secret"
+        );
+        assert_eq!(*store.get_password(SERVICE, account).unwrap(), "secret");
+
+        assert_eq!(
+            get(
+                &store,
+                SERVICE,
+                account,
+                &key_material(),
+                &options(false, Some(IV_B64))
+            )
+            .unwrap(),
+            "secret"
+        );
+        assert_eq!(stored_enc_type(&store, account), 2);
+    }
+
+    #[test]
+    fn test_get_strict_refuses_plaintext_secret() {
+        let account = "ProtectedSecretStrict";
+        let store = MemoryStore::new();
+        store.set_password(SERVICE, account, "secret").unwrap();
+
+        assert!(get(
+            &store,
+            SERVICE,
+            account,
+            &key_material(),
+            &options(true, Some(IV_B64))
+        )
+        .is_err());
+        assert_eq!(*store.get_password(SERVICE, account).unwrap(), "secret");
+    }
+
+    #[test]
+    fn key_material_produces_valid_key() {
+        let result = key_material().derive_key().unwrap();
+        assert_eq!(result.len(), 32);
+    }
+
+    #[test]
+    fn key_material_uses_os_part() {
+        let mut key_material = key_material();
+        let result = key_material.derive_key().unwrap();
+        key_material.os_key_part_b64 = "BAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned();
+        let result2 = key_material.derive_key().unwrap();
+        assert_ne!(result, result2);
+    }
+
+    #[test]
+    fn key_material_uses_client_part() {
+        let mut key_material = key_material();
+        let result = key_material.derive_key().unwrap();
+        key_material.client_key_part_b64 =
+            Some("BAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned());
+        let result2 = key_material.derive_key().unwrap();
+        assert_ne!(result, result2);
+    }
+
+    #[test]
+    fn key_material_produces_consistent_os_only_key() {
+        let mut key_material = key_material();
+        key_material.client_key_part_b64 = None;
+        let result = key_material.derive_key().unwrap();
+        assert_eq!(
+            *result,
+            [
+                81, 100, 62, 172, 151, 119, 182, 58, 123, 38, 129, 116, 209, 253, 66, 118, 218,
+                237, 236, 155, 201, 234, 11, 198, 229, 171, 246, 144, 71, 188, 84, 246
+            ]
+        );
+    }
+
+    #[test]
+    fn key_material_produces_unique_os_only_key() {
+        let mut key_material = key_material();
+        key_material.client_key_part_b64 = None;
+        let result = key_material.derive_key().unwrap();
+        key_material.os_key_part_b64 = "BAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned();
+        let result2 = key_material.derive_key().unwrap();
+        assert_ne!(result, result2);
+    }
+}
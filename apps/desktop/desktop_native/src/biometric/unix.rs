@@ -1,17 +1,40 @@
 use anyhow::{bail, Result};
+use gio::DBusConnection;
 
-use crate::biometrics::{KeyMaterial, OsDerivedKey};
+use super::{dbus, fprint::Fprint, polkit::Polkit};
+use crate::biometrics::{
+    BiometricSecretOptions, BiometricStatus, BiometricStatusReason, KeyMaterial, OsDerivedKey,
+    PromptOutcome,
+};
 
 /// The Unix implementation of the biometric trait.
 pub struct Biometric {}
 
 impl super::BiometricTrait for Biometric {
-    fn prompt(_hwnd: Vec<u8>, _message: String) -> Result<bool> {
-        bail!("platform not supported");
+    fn prompt(_hwnd: Vec<u8>, message: String) -> Result<PromptOutcome> {
+        match dbus::system_bus() {
+            Ok(connection) => prompt_with(connection, &message),
+            Err(_) => Ok(PromptOutcome::Unavailable),
+        }
     }
 
-    fn available() -> Result<bool> {
-        bail!("platform not supported");
+    fn available() -> Result<BiometricStatus> {
+        // Without a system bus there is neither fprintd nor polkit to ask
+        let connection = match dbus::system_bus() {
+            Ok(connection) => connection,
+            Err(_) => return Ok(BiometricStatusReason::Unsupported.into()),
+        };
+
+        // Either needs to be set up, i.e. a finger enrolled or the polkit action installed. Like the
+        // prompt, a failing fprintd leaves polkit
+        let reason = if Fprint::new(connection.clone()).available().unwrap_or(false)
+            || Polkit::new(connection).available()?
+        {
+            BiometricStatusReason::Available
+        } else {
+            BiometricStatusReason::NotConfigured
+        };
+        Ok(reason.into())
     }
 
     fn derive_key_material(_iv_str: Option<&str>) -> Result<OsDerivedKey> {
@@ -22,6 +45,7 @@ impl super::BiometricTrait for Biometric {
         _service: &str,
         _account: &str,
         _key_material: Option<KeyMaterial>,
+        _options: Option<BiometricSecretOptions>,
     ) -> Result<String> {
         bail!("platform not supported");
     }
@@ -36,3 +60,60 @@ impl super::BiometricTrait for Biometric {
         bail!("platform not supported");
     }
 }
+
+// Verifies a fingerprint with fprintd when there is a reader with enrolled prints, and otherwise
+// has polkit authenticate the user, e.g. with their password. fprintd has no UI of its own, so the
+// message is only shown by polkit
+fn prompt_with(connection: DBusConnection, message: &str) -> Result<PromptOutcome> {
+    // A failing fprintd, whether it can't tell if there is a reader or the reader fails while
+    // verifying, still leaves polkit to verify the user with
+    let fprint = Fprint::new(connection.clone());
+    if fprint.available().unwrap_or(false) {
+        if let Ok(outcome) = fprint.verify() {
+            return Ok(outcome);
+        }
+    }
+
+    let polkit = Polkit::new(connection);
+    if !polkit.available()? {
+        return Ok(PromptOutcome::NotConfigured);
+    }
+
+    polkit.check_authorization(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::biometric::{
+        dbus::test_util::PrivateBus,
+        fprint::test_util::{self as fprintd, MockDevice},
+        polkit::test_util::{self as polkitd, MockAuthority, Response, MESSAGE},
+    };
+
+    #[test]
+    fn test_prompt_falls_back_to_polkit_when_verify_fails() {
+        let mut bus = PrivateBus::start();
+        fprintd::serve(
+            &mut bus,
+            Some(MockDevice {
+                enrolled: vec!["right-index-finger"],
+                in_use: false,
+                statuses: vec![("verify-disconnected", true)],
+            }),
+        );
+        polkitd::serve(
+            &mut bus,
+            MockAuthority {
+                action_installed: true,
+                in_session: true,
+                agent: Some(Response::Authenticate),
+            },
+        );
+
+        assert_eq!(
+            prompt_with(bus.connect(), MESSAGE).unwrap(),
+            PromptOutcome::Verified
+        );
+    }
+}
@@ -1,9 +1,5 @@
-use std::str::FromStr;
-
-use aes::cipher::generic_array::GenericArray;
 use anyhow::{anyhow, Result};
 use base64::{engine::general_purpose::STANDARD as base64_engine, Engine};
-use rand::RngCore;
 use retry::delay::Fixed;
 use sha2::{Digest, Sha256};
 use windows::{
@@ -29,16 +25,19 @@ use windows::{
     },
 };
 
-use crate::{
-    biometrics::{KeyMaterial, OsDerivedKey},
-    crypto::{self, CipherString},
+use zeroize::Zeroizing;
+
+use super::protected_secret;
+use crate::biometrics::{
+    BiometricSecretOptions, BiometricStatus, BiometricStatusReason, KeyMaterial, OsDerivedKey,
+    PromptOutcome,
 };
 
 /// The Windows OS implementation of the biometric trait.
 pub struct Biometric {}
 
 impl super::BiometricTrait for Biometric {
-    fn prompt(hwnd: Vec<u8>, message: String) -> Result<bool> {
+    fn prompt(hwnd: Vec<u8>, message: String) -> Result<PromptOutcome> {
         let h = isize::from_le_bytes(hwnd.clone().try_into().unwrap());
         let window = HWND(h);
 
@@ -51,20 +50,34 @@ impl super::BiometricTrait for Biometric {
             unsafe { interop.RequestVerificationForWindowAsync(window, &HSTRING::from(message))? };
         let result = operation.get()?;
 
-        match result {
-            UserConsentVerificationResult::Verified => Ok(true),
-            _ => Ok(false),
-        }
+        Ok(match result {
+            UserConsentVerificationResult::Verified => PromptOutcome::Verified,
+            UserConsentVerificationResult::Canceled => PromptOutcome::Canceled,
+            UserConsentVerificationResult::RetriesExhausted => PromptOutcome::RetriesExhausted,
+            UserConsentVerificationResult::DeviceBusy => PromptOutcome::DeviceBusy,
+            UserConsentVerificationResult::NotConfiguredForUser => PromptOutcome::NotConfigured,
+            _ => PromptOutcome::Unavailable,
+        })
     }
 
-    fn available() -> Result<bool> {
+    fn available() -> Result<BiometricStatus> {
         let ucv_available = UserConsentVerifier::CheckAvailabilityAsync()?.get()?;
 
-        match ucv_available {
-            UserConsentVerifierAvailability::Available => Ok(true),
-            UserConsentVerifierAvailability::DeviceBusy => Ok(true), // TODO: Look into removing this and making the check more ad-hoc
-            _ => Ok(false),
-        }
+        let reason = match ucv_available {
+            UserConsentVerifierAvailability::Available => BiometricStatusReason::Available,
+            UserConsentVerifierAvailability::DeviceBusy => BiometricStatusReason::DeviceBusy,
+            UserConsentVerifierAvailability::DeviceNotPresent => {
+                BiometricStatusReason::DeviceNotPresent
+            }
+            UserConsentVerifierAvailability::NotConfiguredForUser => {
+                BiometricStatusReason::NotConfigured
+            }
+            UserConsentVerifierAvailability::DisabledByPolicy => {
+                BiometricStatusReason::DisabledByPolicy
+            }
+            _ => BiometricStatusReason::Unsupported,
+        };
+        Ok(reason.into())
     }
 
     /// Derive the symmetric encryption key from the Windows Hello signature.
@@ -76,13 +89,7 @@ impl super::BiometricTrait for Biometric {
     /// Windows will only sign the challenge if the user has successfully authenticated with Windows,
     /// ensuring user presence.
     fn derive_key_material(challenge_str: Option<&str>) -> Result<OsDerivedKey> {
-        let challenge: [u8; 16] = match challenge_str {
-            Some(challenge_str) => base64_engine
-                .decode(challenge_str)?
-                .try_into()
-                .map_err(|e: Vec<_>| anyhow!("Expect length {}, got {}", 16, e.len()))?,
-            None => random_challenge(),
-        };
+        let challenge = protected_secret::challenge(challenge_str)?;
         let bitwarden = h!("Bitwarden");
 
         let result = KeyCredentialManager::RequestCreateAsync(
@@ -113,8 +120,8 @@ impl super::BiometricTrait for Biometric {
             windows::core::Array::<u8>::with_len(signature_buffer.Length().unwrap() as usize);
         CryptographicBuffer::CopyToByteArray(&signature_buffer, &mut signature_value)?;
 
-        let key = Sha256::digest(&*signature_value);
-        let key_b64 = base64_engine.encode(&key);
+        let key = Zeroizing::new(<[u8; 32]>::from(Sha256::digest(&*signature_value)));
+        let key_b64 = base64_engine.encode(&*key);
         let iv_b64 = base64_engine.encode(&challenge);
         Ok(OsDerivedKey { key_b64, iv_b64 })
     }
@@ -130,63 +137,36 @@ impl super::BiometricTrait for Biometric {
             "Key material is required for Windows Hello protected keys"
         ))?;
 
-        let encrypted_secret = encrypt(secret, &key_material, iv_b64)?;
-        crate::password::set_password(service, account, &encrypted_secret)?;
-        Ok(encrypted_secret)
+        protected_secret::set(
+            &*crate::password::store(),
+            service,
+            account,
+            secret,
+            &key_material,
+            iv_b64,
+        )
     }
 
     fn get_biometric_secret(
         service: &str,
         account: &str,
         key_material: Option<KeyMaterial>,
+        options: Option<BiometricSecretOptions>,
     ) -> Result<String> {
         let key_material = key_material.ok_or(anyhow!(
             "Key material is required for Windows Hello protected keys"
         ))?;
 
-        let encrypted_secret = crate::password::get_password(service, account)?;
-        match CipherString::from_str(&encrypted_secret) {
-            Ok(secret) => {
-                // If the secret is a CipherString, it is encrypted and we need to decrypt it.
-                let secret = decrypt(&secret, &key_material)?;
-                return Ok(secret);
-            }
-            Err(_) => {
-                // If the secret is not a CipherString, it is not encrypted and we can return it
-                //  directly.
-                return Ok(encrypted_secret);
-            }
-        }
-    }
-}
-
-fn encrypt(secret: &str, key_material: &KeyMaterial, iv_b64: &str) -> Result<String> {
-    let iv = base64_engine
-        .decode(iv_b64)?
-        .try_into()
-        .map_err(|e: Vec<_>| anyhow!("Expected length {}, got {}", 16, e.len()))?;
-
-    let encrypted = crypto::encrypt_aes256(secret.as_bytes(), iv, key_material.derive_key()?)?;
-
-    Ok(encrypted.to_string())
-}
-
-fn decrypt(secret: &CipherString, key_material: &KeyMaterial) -> Result<String> {
-    if let CipherString::AesCbc256_B64 { iv, data } = secret {
-        let decrypted = crypto::decrypt_aes256(&iv, &data, key_material.derive_key()?)?;
-
-        Ok(String::from_utf8(decrypted)?)
-    } else {
-        Err(anyhow!("Invalid cipher string"))
+        protected_secret::get(
+            &*crate::password::store(),
+            service,
+            account,
+            &key_material,
+            &options.unwrap_or_default(),
+        )
     }
 }
 
-fn random_challenge() -> [u8; 16] {
-    let mut challenge = [0u8; 16];
-    rand::thread_rng().fill_bytes(&mut challenge);
-    challenge
-}
-
 /// Searches for a window that looks like a security prompt and set it as focused.
 ///
 /// Gives up after 1.5 seconds with a delay of 500ms between each try.
@@ -238,24 +218,13 @@ fn set_focus(window: HWND) {
     }
 }
 
-impl KeyMaterial {
-    fn digest_material(&self) -> String {
-        match self.client_key_part_b64.as_deref() {
-            Some(client_key_part_b64) => format!("{}|{}", self.os_key_part_b64, client_key_part_b64),
-            None => self.os_key_part_b64.clone(),
-        }
-    }
-
-    pub fn derive_key(&self) -> Result<GenericArray<u8, typenum::U32>> {
-        Ok(Sha256::digest(self.digest_material()))
-    }
-}
-
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
+
     use super::*;
 
-    use crate::biometric::BiometricTrait;
+    use crate::{biometric::BiometricTrait, crypto::CipherString};
 
     #[test]
     #[cfg(feature = "manual_test")]
@@ -290,43 +259,12 @@ mod tests {
     #[test]
     #[cfg(feature = "manual_test")]
     fn test_available() {
-        assert!(<Biometric as BiometricTrait>::available().unwrap())
-    }
-
-    #[test]
-    fn test_encrypt() {
-        let key_material = KeyMaterial {
-            os_key_part_b64: "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned(),
-            client_key_part_b64: Some("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned()),
-        };
-        let iv_b64 = "l9fhDUP/wDJcKwmEzcb/3w==".to_owned();
-        let secret = encrypt("secret", &key_material, &iv_b64)
-            .unwrap()
-            .parse::<CipherString>()
-            .unwrap();
-
-        match secret {
-            CipherString::AesCbc256_B64 { iv, data: _ } => {
-                assert_eq!(iv_b64, base64_engine.encode(&iv));
-            }
-            _ => panic!("Invalid cipher string"),
-        }
-    }
-
-    #[test]
-    fn test_decrypt() {
-        let secret =
-            CipherString::from_str("0.l9fhDUP/wDJcKwmEzcb/3w==|uP4LcqoCCj5FxBDP77NV6Q==").unwrap(); // output from test_encrypt
-        let key_material = KeyMaterial {
-            os_key_part_b64: "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned(),
-            client_key_part_b64: Some("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned()),
-        };
-        assert_eq!(decrypt(&secret, &key_material).unwrap(), "secret")
+        assert!(<Biometric as BiometricTrait>::available().unwrap().available)
     }
 
     #[test]
     fn get_biometric_secret_requires_key() {
-        let result = <Biometric as BiometricTrait>::get_biometric_secret("", "", None);
+        let result = <Biometric as BiometricTrait>::get_biometric_secret("", "", None, None);
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err().to_string(),
@@ -346,9 +284,13 @@ mod tests {
             client_key_part_b64: Some("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned()),
         };
         crate::password::set_password(test, test, secret).unwrap();
-        let result =
-            <Biometric as BiometricTrait>::get_biometric_secret(test, test, Some(key_material))
-                .unwrap();
+        let result = <Biometric as BiometricTrait>::get_biometric_secret(
+            test,
+            test,
+            Some(key_material),
+            None,
+        )
+        .unwrap();
         assert_eq!(result, secret);
     }
 
@@ -366,9 +308,13 @@ mod tests {
         };
         crate::password::set_password(test, test, &secret.to_string()).unwrap();
 
-        let result =
-            <Biometric as BiometricTrait>::get_biometric_secret(test, test, Some(key_material))
-                .unwrap();
+        let result = <Biometric as BiometricTrait>::get_biometric_secret(
+            test,
+            test,
+            Some(key_material),
+            None,
+        )
+        .unwrap();
         assert_eq!(result, "secret");
     }
 
@@ -381,54 +327,4 @@ mod tests {
             "Key material is required for Windows Hello protected keys"
         );
     }
-
-    fn key_material() -> KeyMaterial {
-        KeyMaterial {
-            os_key_part_b64: "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned(),
-            client_key_part_b64: Some("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned()),
-        }
-    }
-
-    #[test]
-    fn key_material_produces_valid_key() {
-        let result = key_material().derive_key().unwrap();
-        assert_eq!(result.len(), 32);
-    }
-
-    #[test]
-    fn key_material_uses_os_part() {
-        let mut key_material = key_material();
-        let result = key_material.derive_key().unwrap();
-        key_material.os_key_part_b64 = "BAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned();
-        let result2 = key_material.derive_key().unwrap();
-        assert_ne!(result, result2);
-    }
-
-    #[test]
-    fn key_material_uses_client_part() {
-        let mut key_material = key_material();
-        let result = key_material.derive_key().unwrap();
-        key_material.client_key_part_b64 =
-            Some("BAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned());
-        let result2 = key_material.derive_key().unwrap();
-        assert_ne!(result, result2);
-    }
-
-    #[test]
-    fn key_material_produces_consistent_os_only_key() {
-        let mut key_material = key_material();
-        key_material.client_key_part_b64 = None;
-        let result = key_material.derive_key().unwrap();
-        assert_eq!(result, [81, 100, 62, 172, 151, 119, 182, 58, 123, 38, 129, 116, 209, 253, 66, 118, 218, 237, 236, 155, 201, 234, 11, 198, 229, 171, 246, 144, 71, 188, 84, 246].into());
-    }
-
-    #[test]
-    fn key_material_produces_unique_os_only_key() {
-        let mut key_material = key_material();
-        key_material.client_key_part_b64 = None;
-        let result = key_material.derive_key().unwrap();
-        key_material.os_key_part_b64 = "BAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned();
-        let result2 = key_material.derive_key().unwrap();
-        assert_ne!(result, result2);
-    }
 }
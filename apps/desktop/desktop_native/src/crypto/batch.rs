@@ -0,0 +1,112 @@
+//! Decryption of many CipherStrings at once, used when loading large vaults.
+
+use std::sync::Arc;
+
+use crate::error::{CryptoError, Result};
+
+use super::{decrypt, CipherString, SymmetricCryptoKey};
+
+/// Decrypt the CipherStrings to UTF-8 on the tokio blocking pool. The batch is split into one
+/// chunk per available core, and every item gets its own result so a single corrupt field does
+/// not fail the whole batch. Results are returned in the same order as the input.
+pub async fn decrypt_batch_to_utf8(
+    cipher_strings: Vec<String>,
+    key: Arc<SymmetricCryptoKey>,
+) -> Vec<Result<String>> {
+    if cipher_strings.is_empty() {
+        return Vec::new();
+    }
+
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = cipher_strings.len().div_ceil(workers);
+
+    let mut cipher_strings = cipher_strings.into_iter();
+    let mut handles = Vec::new();
+    loop {
+        let chunk: Vec<String> = cipher_strings.by_ref().take(chunk_size).collect();
+        if chunk.is_empty() {
+            break;
+        }
+
+        let key = key.clone();
+        let len = chunk.len();
+        let handle = tokio::task::spawn_blocking(move || {
+            chunk
+                .iter()
+                .map(|cipher_string| decrypt_to_utf8(cipher_string, &key))
+                .collect::<Vec<_>>()
+        });
+        handles.push((handle, len));
+    }
+
+    let mut results = Vec::new();
+    for (handle, len) in handles {
+        match handle.await {
+            Ok(chunk) => results.extend(chunk),
+            // A panicking worker only fails the items it was responsible for
+            Err(_) => results.extend((0..len).map(|_| Err(CryptoError::KeyDecrypt.into()))),
+        }
+    }
+    results
+}
+
+fn decrypt_to_utf8(cipher_string: &str, key: &SymmetricCryptoKey) -> Result<String> {
+    let cipher: CipherString = cipher_string.parse()?;
+    let decrypted = decrypt(&cipher, key)?;
+
+    String::from_utf8(decrypted.to_vec()).map_err(|_| CryptoError::InvalidUtf8.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCRYPTED: &str = "2.l9fhDUP/wDJcKwmEzcb/3w==|1NU8iOzb5qO1tS8n4bm/eA==|pt+5ZlFH1QUSzEyGYFmJfMlxnMmebDRVPlHVfuWgfPw=";
+
+    fn user_key() -> Arc<SymmetricCryptoKey> {
+        let key: Vec<u8> = (0..64).collect();
+        Arc::new(SymmetricCryptoKey::try_from(key.as_slice()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_decrypt_batch() {
+        let cipher_strings = vec![ENCRYPTED.to_owned(); 100];
+        let results = decrypt_batch_to_utf8(cipher_strings, user_key()).await;
+
+        assert_eq!(results.len(), 100);
+        for result in results {
+            assert_eq!(result.unwrap(), "EncryptMe!");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decrypt_batch_reports_errors_per_item() {
+        let tampered = ENCRYPTED.replace("pt+5", "pt+6");
+        let cipher_strings = vec![
+            ENCRYPTED.to_owned(),
+            "not a cipher string".to_owned(),
+            tampered,
+            ENCRYPTED.to_owned(),
+        ];
+        let results = decrypt_batch_to_utf8(cipher_strings, user_key()).await;
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_deref().unwrap(), "EncryptMe!");
+        assert!(matches!(
+            results[1],
+            Err(crate::error::Error::InvalidCipherString(_))
+        ));
+        assert!(matches!(
+            results[2],
+            Err(crate::error::Error::Crypto(CryptoError::InvalidMac))
+        ));
+        assert_eq!(results[3].as_deref().unwrap(), "EncryptMe!");
+    }
+
+    #[tokio::test]
+    async fn test_decrypt_batch_empty() {
+        assert!(decrypt_batch_to_utf8(Vec::new(), user_key())
+            .await
+            .is_empty());
+    }
+}
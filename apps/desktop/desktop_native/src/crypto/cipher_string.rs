@@ -123,7 +123,24 @@ impl FromStr for CipherString {
                 }
             }
             ("5" | "6", 2) => {
-                unimplemented!()
+                let data_str = parts[0];
+                let mac_str = parts[1];
+
+                let mac = base64_engine
+                    .decode(mac_str)
+                    .map_err(CSParseError::InvalidBase64)?
+                    .try_into()
+                    .map_err(invalid_len_error(32))?;
+
+                let data = base64_engine
+                    .decode(data_str)
+                    .map_err(CSParseError::InvalidBase64)?;
+
+                if enc_type == "5" {
+                    Ok(CipherString::Rsa2048_OaepSha256_HmacSha256_B64 { mac, data })
+                } else {
+                    Ok(CipherString::Rsa2048_OaepSha1_HmacSha256_B64 { mac, data })
+                }
             }
 
             (enc_type, parts) => Err(CSParseError::InvalidType {
@@ -185,7 +202,7 @@ impl Display for CipherString {
 }
 
 impl CipherString {
-    fn enc_type(&self) -> u8 {
+    pub fn enc_type(&self) -> u8 {
         match self {
             CipherString::AesCbc256_B64 { .. } => 0,
             CipherString::AesCbc128_HmacSha256_B64 { .. } => 1,
@@ -210,3 +227,105 @@ impl CipherString {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IV: &str = "l9fhDUP/wDJcKwmEzcb/3w==";
+    const MAC: &str = "WD8ZRbu9+4o8a9vkjFl7LBvlEfZAGTbC8Q3ZBCHzHWo=";
+    const DATA: &str = "uP4LcqoCCj5FxBDP77NV6Q==";
+
+    fn assert_round_trip(s: &str, expected_name: &str) {
+        let cipher = CipherString::from_str(s).unwrap();
+        assert_eq!(cipher.enc_type_name(), expected_name);
+        assert_eq!(cipher.to_string(), s);
+    }
+
+    #[test]
+    fn round_trip_aes_cbc256_b64() {
+        assert_round_trip(&format!("0.{IV}|{DATA}"), "AesCbc256_B64");
+    }
+
+    #[test]
+    fn round_trip_aes_cbc128_hmac_sha256_b64() {
+        assert_round_trip(&format!("1.{IV}|{DATA}|{MAC}"), "AesCbc128_HmacSha256_B64");
+    }
+
+    #[test]
+    fn round_trip_aes_cbc256_hmac_sha256_b64() {
+        assert_round_trip(&format!("2.{IV}|{DATA}|{MAC}"), "AesCbc256_HmacSha256_B64");
+    }
+
+    #[test]
+    fn round_trip_rsa2048_oaep_sha256_b64() {
+        assert_round_trip(&format!("3.{DATA}"), "Rsa2048_OaepSha256_B64");
+    }
+
+    #[test]
+    fn round_trip_rsa2048_oaep_sha1_b64() {
+        assert_round_trip(&format!("4.{DATA}"), "Rsa2048_OaepSha1_B64");
+    }
+
+    #[test]
+    fn round_trip_rsa2048_oaep_sha256_hmac_sha256_b64() {
+        assert_round_trip(
+            &format!("5.{DATA}|{MAC}"),
+            "Rsa2048_OaepSha256_HmacSha256_B64",
+        );
+    }
+
+    #[test]
+    fn round_trip_rsa2048_oaep_sha1_hmac_sha256_b64() {
+        assert_round_trip(
+            &format!("6.{DATA}|{MAC}"),
+            "Rsa2048_OaepSha1_HmacSha256_B64",
+        );
+    }
+
+    #[test]
+    fn parse_rejects_invalid_mac_length() {
+        let result = CipherString::from_str(&format!("5.{DATA}|{IV}"));
+        assert!(matches!(
+            result,
+            Err(Error::InvalidCipherString(
+                CSParseError::InvalidBase64Length {
+                    expected: 32,
+                    got: 16
+                }
+            ))
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_invalid_base64() {
+        let result = CipherString::from_str(&format!("6.{DATA}|not base64!"));
+        assert!(matches!(
+            result,
+            Err(Error::InvalidCipherString(CSParseError::InvalidBase64(_)))
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_wrong_part_count() {
+        for s in [
+            format!("5.{DATA}"),
+            format!("6.{DATA}|{MAC}|{MAC}"),
+            format!("2.{IV}|{DATA}"),
+            format!("7.{DATA}"),
+        ] {
+            assert!(matches!(
+                CipherString::from_str(&s),
+                Err(Error::InvalidCipherString(CSParseError::InvalidType { .. }))
+            ));
+        }
+    }
+
+    #[test]
+    fn parse_rejects_missing_type() {
+        assert!(matches!(
+            CipherString::from_str(DATA),
+            Err(Error::InvalidCipherString(CSParseError::NoType))
+        ));
+    }
+}
@@ -4,23 +4,33 @@ use aes::cipher::{
     block_padding::Pkcs7, generic_array::GenericArray, typenum::U32, BlockDecryptMut,
     BlockEncryptMut, KeyIvInit,
 };
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use rsa::{
+    pkcs8::{DecodePrivateKey, DecodePublicKey},
+    Oaep, RsaPrivateKey, RsaPublicKey,
+};
+use sha1::Sha1;
+use sha2::Sha256;
+use zeroize::Zeroizing;
 
 use crate::error::{CryptoError, Result};
 
-use super::CipherString;
+use super::{CipherString, SymmetricCryptoKey};
 
 pub fn decrypt_aes256(
     iv: &[u8; 16],
     data: &Vec<u8>,
-    key: GenericArray<u8, U32>,
-) -> Result<Vec<u8>> {
+    key: &GenericArray<u8, U32>,
+) -> Result<Zeroizing<Vec<u8>>> {
     let iv = GenericArray::from_slice(iv);
-    let mut data = data.clone();
-    let decrypted_key_slice = cbc::Decryptor::<aes::Aes256>::new(&key, iv)
+    let mut data = Zeroizing::new(data.clone());
+    let decrypted_key_slice = cbc::Decryptor::<aes::Aes256>::new(key, iv)
         .decrypt_padded_mut::<Pkcs7>(&mut data)
         .map_err(|_| CryptoError::KeyDecrypt)?;
 
     // Data is decrypted in place and returns a subslice of the original Vec, to avoid cloning it, we truncate to the subslice length
+    // The truncated padding is still wiped on drop, since zeroizing a Vec covers its whole capacity
     let decrypted_len = decrypted_key_slice.len();
     data.truncate(decrypted_len);
 
@@ -30,10 +40,363 @@ pub fn decrypt_aes256(
 pub fn encrypt_aes256(
     data_dec: &[u8],
     iv: [u8; 16],
-    key: GenericArray<u8, U32>,
+    key: &GenericArray<u8, U32>,
 ) -> Result<CipherString> {
-    let data = cbc::Encryptor::<aes::Aes256>::new(&key, &iv.into())
+    let data = cbc::Encryptor::<aes::Aes256>::new(key, &iv.into())
         .encrypt_padded_vec_mut::<Pkcs7>(data_dec);
 
     Ok(CipherString::AesCbc256_B64 { iv, data })
 }
+
+/// Decrypt an `AesCbc256_HmacSha256_B64` payload. The MAC is verified before any decryption
+/// takes place.
+pub fn decrypt_aes256_hmac(
+    iv: &[u8; 16],
+    mac: &[u8; 32],
+    data: &Vec<u8>,
+    mac_key: &GenericArray<u8, U32>,
+    key: &GenericArray<u8, U32>,
+) -> Result<Zeroizing<Vec<u8>>> {
+    // `verify_slice` compares the tags in constant time
+    hmac_sha256(iv, data, mac_key)?
+        .verify_slice(mac)
+        .map_err(|_| CryptoError::InvalidMac)?;
+
+    decrypt_aes256(iv, data, key)
+}
+
+/// Encrypt `data_dec` and authenticate the result, producing an `AesCbc256_HmacSha256_B64`.
+pub fn encrypt_aes256_hmac(
+    data_dec: &[u8],
+    iv: [u8; 16],
+    mac_key: &GenericArray<u8, U32>,
+    key: &GenericArray<u8, U32>,
+) -> Result<CipherString> {
+    let data = cbc::Encryptor::<aes::Aes256>::new(key, &iv.into())
+        .encrypt_padded_vec_mut::<Pkcs7>(data_dec);
+
+    let mac = hmac_sha256(&iv, &data, mac_key)?
+        .finalize()
+        .into_bytes()
+        .into();
+
+    Ok(CipherString::AesCbc256_HmacSha256_B64 { iv, mac, data })
+}
+
+/// Encrypt `data_dec` using a random IV. The type of the resulting cipher string depends on
+/// whether the key has a MAC key.
+pub fn encrypt(data_dec: &[u8], key: &SymmetricCryptoKey) -> Result<CipherString> {
+    let mut iv = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut iv);
+
+    match key.mac_key() {
+        Some(mac_key) => encrypt_aes256_hmac(data_dec, iv, mac_key, key.key()),
+        None => encrypt_aes256(data_dec, iv, key.key()),
+    }
+}
+
+/// Decrypt a symmetric cipher string. The key must match the encryption type of the cipher string.
+pub fn decrypt(cipher: &CipherString, key: &SymmetricCryptoKey) -> Result<Zeroizing<Vec<u8>>> {
+    match (cipher, key.mac_key()) {
+        (CipherString::AesCbc256_B64 { iv, data }, None) => decrypt_aes256(iv, data, key.key()),
+        (CipherString::AesCbc256_HmacSha256_B64 { iv, mac, data }, Some(mac_key)) => {
+            decrypt_aes256_hmac(iv, mac, data, mac_key, key.key())
+        }
+        (CipherString::AesCbc256_B64 { .. } | CipherString::AesCbc256_HmacSha256_B64 { .. }, _) => {
+            Err(CryptoError::InvalidKey.into())
+        }
+        _ => Err(CryptoError::InvalidEncType.into()),
+    }
+}
+
+/// The MAC covers the IV followed by the ciphertext.
+pub(super) fn hmac_sha256(
+    iv: &[u8],
+    data: &[u8],
+    mac_key: &GenericArray<u8, U32>,
+) -> Result<Hmac<Sha256>> {
+    let mut hmac = Hmac::<Sha256>::new_from_slice(mac_key).map_err(|_| CryptoError::InvalidKey)?;
+    hmac.update(iv);
+    hmac.update(data);
+    Ok(hmac)
+}
+
+/// Decrypt a RSA-OAEP cipher string using a PKCS#8 DER encoded private key.
+pub fn decrypt_rsa2048_oaep(
+    cipher: &CipherString,
+    private_key_der: &[u8],
+) -> Result<Zeroizing<Vec<u8>>> {
+    let private_key =
+        RsaPrivateKey::from_pkcs8_der(private_key_der).map_err(|_| CryptoError::InvalidKey)?;
+
+    // The HmacSha256 types are deprecated and their MAC is not verified, matching the other clients
+    let (padding, data) = match cipher {
+        CipherString::Rsa2048_OaepSha256_B64 { data }
+        | CipherString::Rsa2048_OaepSha256_HmacSha256_B64 { data, .. } => {
+            (Oaep::new::<Sha256>(), data)
+        }
+        CipherString::Rsa2048_OaepSha1_B64 { data }
+        | CipherString::Rsa2048_OaepSha1_HmacSha256_B64 { data, .. } => (Oaep::new::<Sha1>(), data),
+        _ => return Err(CryptoError::InvalidEncType.into()),
+    };
+
+    Ok(Zeroizing::new(
+        private_key
+            .decrypt(padding, data)
+            .map_err(|_| CryptoError::KeyDecrypt)?,
+    ))
+}
+
+/// Encrypt `data_dec` with a SPKI DER encoded public key, producing an `Rsa2048_OaepSha1_B64`.
+///
+/// This is the type produced by the other clients when sharing keys.
+pub fn encrypt_rsa2048_oaep_sha1(data_dec: &[u8], public_key_der: &[u8]) -> Result<CipherString> {
+    let data = encrypt_rsa2048_oaep(data_dec, public_key_der, Oaep::new::<Sha1>())?;
+    Ok(CipherString::Rsa2048_OaepSha1_B64 { data })
+}
+
+/// Encrypt `data_dec` with a SPKI DER encoded public key, producing an `Rsa2048_OaepSha256_B64`.
+pub fn encrypt_rsa2048_oaep_sha256(data_dec: &[u8], public_key_der: &[u8]) -> Result<CipherString> {
+    let data = encrypt_rsa2048_oaep(data_dec, public_key_der, Oaep::new::<Sha256>())?;
+    Ok(CipherString::Rsa2048_OaepSha256_B64 { data })
+}
+
+fn encrypt_rsa2048_oaep(data_dec: &[u8], public_key_der: &[u8], padding: Oaep) -> Result<Vec<u8>> {
+    let public_key =
+        RsaPublicKey::from_public_key_der(public_key_der).map_err(|_| CryptoError::InvalidKey)?;
+
+    Ok(public_key
+        .encrypt(&mut rand::thread_rng(), padding, data_dec)
+        .map_err(|_| CryptoError::KeyEncrypt)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use base64::{engine::general_purpose::STANDARD as base64_engine, Engine};
+
+    use super::*;
+
+    // Key pair and SHA-1 ciphertext shared with `web-crypto-function.service.spec.ts`
+    const RSA_PUBLIC_KEY: &str = concat!(
+        "MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEAl0Vawl/toXzkEvB82FEtqHP",
+        "4xlU2ab/v0crqIfXfIoWF/XXdHGIdrZeilnRXPPJT1B9dTsasttEZNnua/0Rek/cjNDHtzT52irfoZYS7X6HNIfOi54Q+egP",
+        "RQ1H7iNHVZz3K8Db9GCSKPeC8MbW6gVCzb15esCe1gGzg6wkMuWYDFYPoh/oBqcIqrGah7firqB1nDedzEjw32heP2DAffVN",
+        "084iTDjiWrJNUxBJ2pDD5Z9dT3MzQ2s09ew1yMWK2z37rT3YerC7OgEDmo3WYo3xL3qYJznu3EO2nmrYjiRa40wKSjxsTlUc",
+        "xDF+F0uMW8oR9EMUHgepdepfAtLsSAQIDAQAB",
+    );
+    const RSA_PRIVATE_KEY: &str = concat!(
+        "MIIEvQIBADANBgkqhkiG9w0BAQEFAASCBKcwggSjAgEAAoIBAQCXRVrCX+2hfOQS8Hz",
+        "YUS2oc/jGVTZpv+/Ryuoh9d8ihYX9dd0cYh2tl6KWdFc88lPUH11Oxqy20Rk2e5r/RF6T9yM0Me3NPnaKt+hlhLtfoc0h86L",
+        "nhD56A9FDUfuI0dVnPcrwNv0YJIo94LwxtbqBULNvXl6wJ7WAbODrCQy5ZgMVg+iH+gGpwiqsZqHt+KuoHWcN53MSPDfaF4/",
+        "YMB99U3TziJMOOJask1TEEnakMPln11PczNDazT17DXIxYrbPfutPdh6sLs6AQOajdZijfEvepgnOe7cQ7aeatiOJFrjTApK",
+        "PGxOVRzEMX4XS4xbyhH0QxQeB6l16l8C0uxIBAgMBAAECggEASaWfeVDA3cVzOPFSpvJm20OTE+R6uGOU+7vh36TX/POq92q",
+        "Buwbd0h0oMD32FxsXywd2IxtBDUSiFM9699qufTVuM0Q3tZw6lHDTOVG08+tPdr8qSbMtw7PGFxN79fHLBxejjO4IrM9lapj",
+        "WpxEF+11x7r+wM+0xRZQ8sNFYG46aPfIaty4BGbL0I2DQ2y8I57iBCAy69eht59NLMm27fRWGJIWCuBIjlpfzET1j2HLXUIh",
+        "5bTBNzqaN039WH49HczGE3mQKVEJZc/efk3HaVd0a1Sjzyn0QY+N1jtZN3jTRbuDWA1AknkX1LX/0tUhuS3/7C3ejHxjw4Dk",
+        "1ZLo5/QKBgQDIWvqFn0+IKRSu6Ua2hDsufIHHUNLelbfLUMmFthxabcUn4zlvIscJO00Tq/ezopSRRvbGiqnxjv/mYxucvOU",
+        "BeZtlus0Q9RTACBtw9TGoNTmQbEunJ2FOSlqbQxkBBAjgGEppRPt30iGj/VjAhCATq2MYOa/X4dVR51BqQAFIEwKBgQDBSIf",
+        "TFKC/hDk6FKZlgwvupWYJyU9RkyfstPErZFmzoKhPkQ3YORo2oeAYmVUbS9I2iIYpYpYQJHX8jMuCbCz4ONxTCuSIXYQYUcU",
+        "q4PglCKp31xBAE6TN8SvhfME9/MvuDssnQinAHuF0GDAhF646T3LLS1not6Vszv7brwSoGwKBgQC88v/8cGfi80ssQZeMnVv",
+        "q1UTXIeQcQnoY5lGHJl3K8mbS3TnXE6c9j417Fdz+rj8KWzBzwWXQB5pSPflWcdZO886Xu/mVGmy9RWgLuVFhXwCwsVEPjNX",
+        "5ramRb0/vY0yzenUCninBsIxFSbIfrPtLUYCc4hpxr+sr2Mg/y6jpvQKBgBezMRRs3xkcuXepuI2R+BCXL1/b02IJTUf1F+1",
+        "eLLGd7YV0H+J3fgNc7gGWK51hOrF9JBZHBGeOUPlaukmPwiPdtQZpu4QNE3l37VlIpKTF30E6mb+BqR+nht3rUjarnMXgAoE",
+        "Z18y6/KIjpSMpqC92Nnk/EBM9EYe6Cf4eA9ApAoGAeqEUg46UTlJySkBKURGpIs3v1kkf5I0X8DnOhwb+HPxNaiEdmO7ckm8",
+        "+tPVgppLcG0+tMdLjigFQiDUQk2y3WjyxP5ZvXu7U96jaJRI8PFMoE06WeVYcdIzrID2HvqH+w0UQJFrLJ/0Mn4stFAEzXKZ",
+        "BokBGnjFnTnKcs7nv/O8=",
+    );
+    const RSA_SHA1_ENCRYPTED: &str = concat!(
+        "4.A1/p8BQzN9UrbdYxUY2Va5+kPLyfZXF9JsZrjeEXcaclsnHurdxVAJcnbEqYMP3UXV",
+        "4YAS/mpf+Rxe6/X0WS1boQdA0MAHSgx95hIlAraZYpiMLLiJRKeo2u8YivCdTM9V5vuAEJwf9Tof/qFsFci3sApdbATkorCT",
+        "zFOIEPF2S1zgperEP23M01mr4dWVdYN18B32YF67xdJHMbFhp5dkQwv9CmscoWq7OE5HIfOb+JAh7BEZb+CmKhM3yWJvoR/D",
+        "/5jcercUtK2o+XrzNrL4UQ7yLZcFz6Bfwb/j6ICYvqd/YJwXNE6dwlL57OfwJyCdw2rRYf0/qI00t9u8Iitw==",
+    );
+    // Encrypted with the same public key, no TS spec covers OAEP with SHA-256
+    const RSA_SHA256_ENCRYPTED: &str = concat!(
+        "3.Dtw7jO77O91YyVdTzdKh4/ZUR363k69E2Ti++HgfyB+oGM+w0E39kzWvfQI0fNYSkXWDMVXthH8UvvaJVbhYhaDehkZE9W",
+        "Ka1Awns+Nemkv/VcAIX/6Pr2h78hgRnpVfcwLq179QriknckQkzhEjBYQ9x8uaVIGJhSj7eH/yW6EvApj30I4fJtdEUaq3Z8",
+        "zePzrFBC2vwaCtCT2KibDR85Hz19By2wceZopBF+uBssAf9uKrzOz/3oixZ+JyWaZEiujJYXGDL8ppcHF/uJ8FMUkvg6Iri8",
+        "oH951Cui7w2U5jBmBbEL+aQkpBUtheNTTd5DX5Z+sb6XojItmxEGXtXA==",
+    );
+
+    // Generated with an independent AES-CBC/HMAC-SHA256 implementation
+    const ENCRYPTED: &str = "2.l9fhDUP/wDJcKwmEzcb/3w==|1NU8iOzb5qO1tS8n4bm/eA==|pt+5ZlFH1QUSzEyGYFmJfMlxnMmebDRVPlHVfuWgfPw=";
+
+    fn keys() -> (GenericArray<u8, U32>, GenericArray<u8, U32>) {
+        let key: [u8; 32] = core::array::from_fn(|i| i as u8);
+        let mac_key: [u8; 32] = core::array::from_fn(|i| i as u8 + 32);
+        (mac_key.into(), key.into())
+    }
+
+    fn parts(cipher: CipherString) -> ([u8; 16], [u8; 32], Vec<u8>) {
+        match cipher {
+            CipherString::AesCbc256_HmacSha256_B64 { iv, mac, data } => (iv, mac, data),
+            _ => panic!("Invalid cipher string"),
+        }
+    }
+
+    #[test]
+    fn test_encrypt_aes256_hmac() {
+        let (mac_key, key) = keys();
+        let iv = [
+            0x97, 0xd7, 0xe1, 0x0d, 0x43, 0xff, 0xc0, 0x32, 0x5c, 0x2b, 0x09, 0x84, 0xcd, 0xc6,
+            0xff, 0xdf,
+        ];
+
+        let result = encrypt_aes256_hmac(b"EncryptMe!", iv, &mac_key, &key).unwrap();
+        assert_eq!(result.to_string(), ENCRYPTED);
+    }
+
+    #[test]
+    fn test_decrypt_aes256_hmac() {
+        let (mac_key, key) = keys();
+        let (iv, mac, data) = parts(CipherString::from_str(ENCRYPTED).unwrap());
+
+        let result = decrypt_aes256_hmac(&iv, &mac, &data, &mac_key, &key).unwrap();
+        assert_eq!(*result, b"EncryptMe!");
+    }
+
+    #[test]
+    fn test_decrypt_aes256_hmac_rejects_tampered_data() {
+        let (mac_key, key) = keys();
+        let (iv, mac, mut data) = parts(CipherString::from_str(ENCRYPTED).unwrap());
+        data[0] ^= 1;
+
+        let result = decrypt_aes256_hmac(&iv, &mac, &data, &mac_key, &key);
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::Crypto(CryptoError::InvalidMac))
+        ));
+    }
+
+    #[test]
+    fn test_decrypt_aes256_hmac_rejects_wrong_mac_key() {
+        let (_, key) = keys();
+        let (iv, mac, data) = parts(CipherString::from_str(ENCRYPTED).unwrap());
+
+        let result = decrypt_aes256_hmac(&iv, &mac, &data, &key, &key);
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::Crypto(CryptoError::InvalidMac))
+        ));
+    }
+
+    fn rsa_private_key() -> Vec<u8> {
+        base64_engine.decode(RSA_PRIVATE_KEY).unwrap()
+    }
+
+    fn rsa_public_key() -> Vec<u8> {
+        base64_engine.decode(RSA_PUBLIC_KEY).unwrap()
+    }
+
+    #[test]
+    fn test_decrypt_rsa2048_oaep_sha1() {
+        let cipher = CipherString::from_str(RSA_SHA1_ENCRYPTED).unwrap();
+
+        let result = decrypt_rsa2048_oaep(&cipher, &rsa_private_key()).unwrap();
+        assert_eq!(*result, b"EncryptMe!");
+    }
+
+    #[test]
+    fn test_decrypt_rsa2048_oaep_sha256() {
+        let cipher = CipherString::from_str(RSA_SHA256_ENCRYPTED).unwrap();
+
+        let result = decrypt_rsa2048_oaep(&cipher, &rsa_private_key()).unwrap();
+        assert_eq!(*result, b"EncryptMe!");
+    }
+
+    #[test]
+    fn test_encrypt_rsa2048_oaep_sha1() {
+        let cipher = encrypt_rsa2048_oaep_sha1(b"EncryptMe!", &rsa_public_key()).unwrap();
+        assert!(matches!(cipher, CipherString::Rsa2048_OaepSha1_B64 { .. }));
+
+        let result = decrypt_rsa2048_oaep(&cipher, &rsa_private_key()).unwrap();
+        assert_eq!(*result, b"EncryptMe!");
+    }
+
+    #[test]
+    fn test_encrypt_rsa2048_oaep_sha256() {
+        let cipher = encrypt_rsa2048_oaep_sha256(b"EncryptMe!", &rsa_public_key()).unwrap();
+        assert!(matches!(
+            cipher,
+            CipherString::Rsa2048_OaepSha256_B64 { .. }
+        ));
+
+        let result = decrypt_rsa2048_oaep(&cipher, &rsa_private_key()).unwrap();
+        assert_eq!(*result, b"EncryptMe!");
+    }
+
+    #[test]
+    fn test_decrypt_rsa2048_oaep_rejects_wrong_hash() {
+        let data = match CipherString::from_str(RSA_SHA1_ENCRYPTED).unwrap() {
+            CipherString::Rsa2048_OaepSha1_B64 { data } => data,
+            _ => panic!("Invalid cipher string"),
+        };
+        let cipher = CipherString::Rsa2048_OaepSha256_B64 { data };
+
+        let result = decrypt_rsa2048_oaep(&cipher, &rsa_private_key());
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::Crypto(CryptoError::KeyDecrypt))
+        ));
+    }
+
+    #[test]
+    fn test_decrypt_rsa2048_oaep_rejects_invalid_key() {
+        let cipher = CipherString::from_str(RSA_SHA1_ENCRYPTED).unwrap();
+
+        let result = decrypt_rsa2048_oaep(&cipher, &rsa_public_key());
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::Crypto(CryptoError::InvalidKey))
+        ));
+    }
+
+    #[test]
+    fn test_decrypt_rsa2048_oaep_rejects_symmetric_cipher_string() {
+        let cipher = CipherString::from_str(ENCRYPTED).unwrap();
+
+        let result = decrypt_rsa2048_oaep(&cipher, &rsa_private_key());
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::Crypto(CryptoError::InvalidEncType))
+        ));
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_with_user_key() {
+        let (mac_key, key) = keys();
+        let user_key = SymmetricCryptoKey::new(key, Some(mac_key));
+
+        let cipher = encrypt(b"EncryptMe!", &user_key).unwrap();
+        assert!(matches!(
+            cipher,
+            CipherString::AesCbc256_HmacSha256_B64 { .. }
+        ));
+        assert_eq!(*decrypt(&cipher, &user_key).unwrap(), b"EncryptMe!");
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_with_enc_only_key() {
+        let (_, key) = keys();
+        let key = SymmetricCryptoKey::new(key, None);
+
+        let cipher = encrypt(b"EncryptMe!", &key).unwrap();
+        assert!(matches!(cipher, CipherString::AesCbc256_B64 { .. }));
+        assert_eq!(*decrypt(&cipher, &key).unwrap(), b"EncryptMe!");
+    }
+
+    #[test]
+    fn test_decrypt_rejects_mismatched_key() {
+        let (_, key) = keys();
+        let key = SymmetricCryptoKey::new(key, None);
+        let cipher = CipherString::from_str(ENCRYPTED).unwrap();
+
+        let result = decrypt(&cipher, &key);
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::Crypto(CryptoError::InvalidKey))
+        ));
+    }
+}
@@ -0,0 +1,475 @@
+//! Streaming encryption of files in the `EncArrayBuffer` format used by attachments and file
+//! Sends.
+//!
+//! The layout is the encryption type byte followed by the IV, the MAC (only present for
+//! `AesCbc256_HmacSha256_B64`) and the ciphertext. The MAC covers the IV and the ciphertext.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use aes::cipher::{
+    block_padding::{Padding, Pkcs7},
+    generic_array::GenericArray,
+    typenum::U16,
+    BlockDecryptMut, BlockEncryptMut, KeyIvInit,
+};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use super::{crypto::hmac_sha256, SymmetricCryptoKey};
+use crate::error::{CryptoError, Result};
+
+const ENC_TYPE_LENGTH: usize = 1;
+const IV_LENGTH: usize = 16;
+const MAC_LENGTH: usize = 32;
+const BLOCK_LENGTH: usize = 16;
+
+/// Files are processed in chunks of this size, it must be a multiple of the AES block size.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Encrypt the file at `input` into `output`. The `progress` callback receives the number of
+/// bytes processed so far and the total number of bytes.
+///
+/// An existing `output` is only replaced once encryption succeeded.
+pub fn encrypt_file(
+    input: &Path,
+    output: &Path,
+    key: &SymmetricCryptoKey,
+    progress: impl FnMut(u64, u64),
+) -> Result<()> {
+    let reader = File::open(input)?;
+    let total = reader.metadata()?.len();
+
+    write_output(input, output, |writer| {
+        encrypt(reader, writer, total, key, progress)
+    })
+}
+
+/// Decrypt the file at `input` into `output`. The `progress` callback receives the number of
+/// bytes processed so far and the total number of bytes.
+///
+/// Authenticated files are read twice, since the MAC is verified before any plaintext is written.
+/// An existing `output` is only replaced once the whole file is decrypted and authenticated.
+pub fn decrypt_file(
+    input: &Path,
+    output: &Path,
+    key: &SymmetricCryptoKey,
+    progress: impl FnMut(u64, u64),
+) -> Result<()> {
+    let reader = File::open(input)?;
+
+    write_output(input, output, |writer| {
+        decrypt(reader, writer, key, progress)
+    })
+}
+
+// Write to a temporary file next to `output`, which is moved over `output` once `write` succeeded,
+// and removed otherwise. `input` can't be the output, as it would be replaced while it is read
+fn write_output(
+    input: &Path,
+    output: &Path,
+    write: impl FnOnce(&File) -> Result<()>,
+) -> Result<()> {
+    if output.exists() && std::fs::canonicalize(input)? == std::fs::canonicalize(output)? {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "the input and output are the same file",
+        )
+        .into());
+    }
+
+    let mut suffix = [0u8; 8];
+    rand::thread_rng().fill_bytes(&mut suffix);
+    let mut temp_name = output.as_os_str().to_owned();
+    temp_name.push(format!(".{}.tmp", u64::from_le_bytes(suffix)));
+    let temp_path = PathBuf::from(temp_name);
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)?;
+    scopeguard::defer!({
+        let _ = std::fs::remove_file(&temp_path);
+    });
+
+    write(&file)?;
+    file.sync_all()?;
+    // Closed first, since Windows can't replace a file that is open
+    drop(file);
+    std::fs::rename(&temp_path, output)?;
+    Ok(())
+}
+
+pub fn encrypt<R: Read, W: Write + Seek>(
+    mut reader: R,
+    mut writer: W,
+    total: u64,
+    key: &SymmetricCryptoKey,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<()> {
+    let mut iv = [0u8; IV_LENGTH];
+    rand::thread_rng().fill_bytes(&mut iv);
+
+    writer.write_all(&[key.enc_type()])?;
+    writer.write_all(&iv)?;
+
+    // The MAC is only known once all the data is encrypted, reserve space for it
+    let mac_position = writer.stream_position()?;
+    let mut hmac = match key.mac_key() {
+        Some(mac_key) => {
+            writer.write_all(&[0u8; MAC_LENGTH])?;
+            Some(hmac_sha256(&iv, &[], mac_key)?)
+        }
+        None => None,
+    };
+
+    let mut encryptor = cbc::Encryptor::<aes::Aes256>::new(key.key(), &iv.into());
+    let mut buffer = Zeroizing::new(vec![0u8; CHUNK_SIZE]);
+    let mut processed = 0u64;
+
+    loop {
+        let read = read_chunk(&mut reader, &mut buffer)?;
+        processed += read as u64;
+
+        // A partial chunk means we reached the end of the input and need to add the padding
+        let len = if read < CHUNK_SIZE {
+            let padded_len = (read / BLOCK_LENGTH + 1) * BLOCK_LENGTH;
+            let padding = GenericArray::<u8, U16>::from_mut_slice(
+                &mut buffer[padded_len - BLOCK_LENGTH..padded_len],
+            );
+            Pkcs7::pad(padding, read % BLOCK_LENGTH);
+            padded_len
+        } else {
+            read
+        };
+
+        for block in buffer[..len].chunks_exact_mut(BLOCK_LENGTH) {
+            encryptor.encrypt_block_mut(GenericArray::from_mut_slice(block));
+        }
+        if let Some(hmac) = hmac.as_mut() {
+            hmac.update(&buffer[..len]);
+        }
+        writer.write_all(&buffer[..len])?;
+
+        progress(processed, total);
+
+        if read < CHUNK_SIZE {
+            break;
+        }
+    }
+
+    if let Some(hmac) = hmac {
+        writer.seek(SeekFrom::Start(mac_position))?;
+        writer.write_all(&hmac.finalize().into_bytes())?;
+        writer.seek(SeekFrom::End(0))?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn decrypt<R: Read + Seek, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    key: &SymmetricCryptoKey,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<()> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut enc_type = [0u8; ENC_TYPE_LENGTH];
+    reader.read_exact(&mut enc_type)?;
+
+    let header_len = match (enc_type[0], key.mac_key()) {
+        (0, None) => ENC_TYPE_LENGTH + IV_LENGTH,
+        (2, Some(_)) => ENC_TYPE_LENGTH + IV_LENGTH + MAC_LENGTH,
+        (0 | 2, _) => return Err(CryptoError::InvalidKey.into()),
+        _ => return Err(CryptoError::InvalidEncArrayBuffer.into()),
+    } as u64;
+
+    // Due to the padding the ciphertext is always at least one whole block
+    let data_len = file_len.saturating_sub(header_len);
+    if data_len == 0 || data_len % BLOCK_LENGTH as u64 != 0 {
+        return Err(CryptoError::InvalidEncArrayBuffer.into());
+    }
+
+    let mut iv = [0u8; IV_LENGTH];
+    reader.read_exact(&mut iv)?;
+
+    let mut buffer = Zeroizing::new(vec![0u8; CHUNK_SIZE]);
+    let total = match key.mac_key() {
+        Some(_) => data_len * 2,
+        None => data_len,
+    };
+
+    let mut mac = None;
+    if let Some(mac_key) = key.mac_key() {
+        let mut expected = [0u8; MAC_LENGTH];
+        reader.read_exact(&mut expected)?;
+
+        let mut hmac = hmac_sha256(&iv, &[], mac_key)?;
+        let mut processed = 0u64;
+        while processed < data_len {
+            let len = next_chunk_len(processed, data_len);
+            reader.read_exact(&mut buffer[..len])?;
+            hmac.update(&buffer[..len]);
+
+            processed += len as u64;
+            progress(processed, total);
+        }
+
+        // `verify_slice` compares the tags in constant time
+        hmac.verify_slice(&expected)
+            .map_err(|_| CryptoError::InvalidMac)?;
+
+        reader.seek(SeekFrom::Start(header_len))?;
+        mac = Some((hmac_sha256(&iv, &[], mac_key)?, expected));
+    }
+
+    let mut decryptor = cbc::Decryptor::<aes::Aes256>::new(key.key(), &iv.into());
+    let mut processed = 0u64;
+    while processed < data_len {
+        let len = next_chunk_len(processed, data_len);
+        reader.read_exact(&mut buffer[..len])?;
+        processed += len as u64;
+
+        // The input is authenticated again while decrypting, in case it changed after verification
+        if let Some((hmac, _)) = mac.as_mut() {
+            hmac.update(&buffer[..len]);
+        }
+
+        for block in buffer[..len].chunks_exact_mut(BLOCK_LENGTH) {
+            decryptor.decrypt_block_mut(GenericArray::from_mut_slice(block));
+        }
+
+        let plaintext_len = if processed == data_len {
+            let last_block = GenericArray::<u8, U16>::from_slice(&buffer[len - BLOCK_LENGTH..len]);
+            let unpadded = Pkcs7::unpad(last_block).map_err(|_| CryptoError::KeyDecrypt)?;
+            len - BLOCK_LENGTH + unpadded.len()
+        } else {
+            len
+        };
+        writer.write_all(&buffer[..plaintext_len])?;
+
+        progress(total - data_len + processed, total);
+    }
+
+    if let Some((hmac, expected)) = mac {
+        Hmac::<Sha256>::verify_slice(hmac, &expected).map_err(|_| CryptoError::InvalidMac)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn next_chunk_len(processed: u64, data_len: u64) -> usize {
+    (data_len - processed).min(CHUNK_SIZE as u64) as usize
+}
+
+/// Fill the buffer as far as possible, only returning less than the buffer size at the end of the
+/// input.
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use base64::{engine::general_purpose::STANDARD as base64_engine, Engine};
+
+    use super::*;
+    use crate::error::Error;
+
+    fn user_key() -> SymmetricCryptoKey {
+        let key: Vec<u8> = (0..64).collect();
+        SymmetricCryptoKey::try_from(key.as_slice()).unwrap()
+    }
+
+    fn round_trip(key: &SymmetricCryptoKey, plaintext: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut encrypted = Cursor::new(Vec::new());
+        encrypt(
+            plaintext,
+            &mut encrypted,
+            plaintext.len() as u64,
+            key,
+            |_, _| {},
+        )
+        .unwrap();
+
+        let mut decrypted = Vec::new();
+        decrypt(
+            Cursor::new(encrypted.get_ref()),
+            &mut decrypted,
+            key,
+            |_, _| {},
+        )
+        .unwrap();
+
+        (encrypted.into_inner(), decrypted)
+    }
+
+    #[test]
+    fn test_round_trip_authenticated() {
+        let plaintext = b"EncryptMe!";
+        let (encrypted, decrypted) = round_trip(&user_key(), plaintext);
+
+        assert_eq!(encrypted[0], 2);
+        assert_eq!(
+            encrypted.len(),
+            ENC_TYPE_LENGTH + IV_LENGTH + MAC_LENGTH + BLOCK_LENGTH
+        );
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn test_round_trip_unauthenticated() {
+        let key = SymmetricCryptoKey::try_from([7u8; 32].as_slice()).unwrap();
+        let plaintext = b"EncryptMe!";
+        let (encrypted, decrypted) = round_trip(&key, plaintext);
+
+        assert_eq!(encrypted[0], 0);
+        assert_eq!(encrypted.len(), ENC_TYPE_LENGTH + IV_LENGTH + BLOCK_LENGTH);
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn test_round_trip_multiple_chunks() {
+        // Cover both a chunk aligned input, which gets a whole block of padding, and a partial one
+        for len in [CHUNK_SIZE * 2, CHUNK_SIZE * 2 + 5] {
+            let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let (encrypted, decrypted) = round_trip(&user_key(), &plaintext);
+
+            let padding = BLOCK_LENGTH - len % BLOCK_LENGTH;
+            assert_eq!(
+                encrypted.len(),
+                ENC_TYPE_LENGTH + IV_LENGTH + MAC_LENGTH + len + padding
+            );
+            assert_eq!(decrypted, plaintext);
+        }
+    }
+
+    #[test]
+    fn test_decrypt_matches_cipher_string_format() {
+        // The `EncryptMe!` vector from the crypto tests, laid out as an EncArrayBuffer
+        let key: Vec<u8> = (0..64).collect();
+        let key = SymmetricCryptoKey::try_from(key.as_slice()).unwrap();
+        let mut encrypted = vec![2u8];
+        for part in [
+            "l9fhDUP/wDJcKwmEzcb/3w==",
+            "pt+5ZlFH1QUSzEyGYFmJfMlxnMmebDRVPlHVfuWgfPw=",
+            "1NU8iOzb5qO1tS8n4bm/eA==",
+        ] {
+            encrypted.extend(base64_engine.decode(part).unwrap());
+        }
+
+        let mut decrypted = Vec::new();
+        decrypt(Cursor::new(encrypted), &mut decrypted, &key, |_, _| {}).unwrap();
+        assert_eq!(decrypted, b"EncryptMe!");
+    }
+
+    #[test]
+    fn test_decrypt_rejects_tampered_data() {
+        let (mut encrypted, _) = round_trip(&user_key(), b"EncryptMe!");
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+
+        let mut decrypted = Vec::new();
+        let result = decrypt(
+            Cursor::new(encrypted),
+            &mut decrypted,
+            &user_key(),
+            |_, _| {},
+        );
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::Crypto(CryptoError::InvalidMac))
+        ));
+        assert!(decrypted.is_empty());
+    }
+
+    #[test]
+    fn test_decrypt_rejects_invalid_format() {
+        for encrypted in [vec![], vec![2u8; 40], vec![5u8; 100]] {
+            let result = decrypt(Cursor::new(encrypted), Vec::new(), &user_key(), |_, _| {});
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn test_progress() {
+        let plaintext = vec![0u8; CHUNK_SIZE + 1];
+        let mut encrypted = Cursor::new(Vec::new());
+        let mut calls = Vec::new();
+        encrypt(
+            plaintext.as_slice(),
+            &mut encrypted,
+            plaintext.len() as u64,
+            &user_key(),
+            |processed, total| calls.push((processed, total)),
+        )
+        .unwrap();
+
+        let total = plaintext.len() as u64;
+        assert_eq!(calls, vec![(CHUNK_SIZE as u64, total), (total, total)]);
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let dir = std::env::temp_dir().join(format!("bitwarden-enc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        scopeguard::defer!(let _ = std::fs::remove_dir_all(&dir););
+
+        let (plain, encrypted, decrypted) = (
+            dir.join("plain"),
+            dir.join("encrypted"),
+            dir.join("decrypted"),
+        );
+        std::fs::write(&plain, b"EncryptMe!").unwrap();
+
+        encrypt_file(&plain, &encrypted, &user_key(), |_, _| {}).unwrap();
+        decrypt_file(&encrypted, &decrypted, &user_key(), |_, _| {}).unwrap();
+        assert_eq!(std::fs::read(&decrypted).unwrap(), b"EncryptMe!");
+
+        // A failed decryption keeps the existing output, and leaves no partial output behind
+        let wrong_key = SymmetricCryptoKey::try_from([1u8; 64].as_slice()).unwrap();
+        assert!(decrypt_file(&encrypted, &decrypted, &wrong_key, |_, _| {}).is_err());
+        assert_eq!(std::fs::read(&decrypted).unwrap(), b"EncryptMe!");
+        std::fs::remove_file(&decrypted).unwrap();
+        assert!(decrypt_file(&encrypted, &decrypted, &wrong_key, |_, _| {}).is_err());
+        assert!(!decrypted.exists());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_file_same_input_and_output() {
+        let dir = std::env::temp_dir().join(format!("bitwarden-enc-same-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        scopeguard::defer!(let _ = std::fs::remove_dir_all(&dir););
+
+        let (plain, encrypted) = (dir.join("plain"), dir.join("encrypted"));
+        std::fs::write(&plain, b"EncryptMe!").unwrap();
+        encrypt_file(&plain, &encrypted, &user_key(), |_, _| {}).unwrap();
+        let contents = std::fs::read(&encrypted).unwrap();
+
+        // Also when the paths differ
+        let same = dir.join(".").join("encrypted");
+        let result = decrypt_file(&encrypted, &same, &user_key(), |_, _| {});
+        assert!(matches!(result, Err(Error::Io(e)) if e.kind() == ErrorKind::InvalidInput));
+        assert_eq!(std::fs::read(&encrypted).unwrap(), contents);
+    }
+}
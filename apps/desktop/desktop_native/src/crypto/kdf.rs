@@ -0,0 +1,190 @@
+//! Master key derivation from the master password
+
+use std::num::NonZeroU32;
+
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::error::{CryptoError, Result};
+
+const PBKDF2_MIN_ITERATIONS: u32 = 5000;
+
+const ARGON2_DEFAULT_MEMORY: u32 = 64;
+const ARGON2_DEFAULT_PARALLELISM: u32 = 4;
+const ARGON2_MIN_ITERATIONS: u32 = 2;
+const ARGON2_MIN_MEMORY: u32 = 16;
+const ARGON2_MAX_MEMORY: u32 = 1024;
+const ARGON2_MIN_PARALLELISM: u32 = 1;
+
+/// Validated KDF parameters, Argon2 memory is in MiB.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Kdf {
+    Pbkdf2 {
+        iterations: NonZeroU32,
+    },
+    Argon2id {
+        iterations: NonZeroU32,
+        memory: NonZeroU32,
+        parallelism: NonZeroU32,
+    },
+}
+
+impl Kdf {
+    /// Apply the same limits as the `CryptoService` in `libs/common`.
+    pub fn pbkdf2(iterations: u32) -> Result<Self, CryptoError> {
+        if iterations < PBKDF2_MIN_ITERATIONS {
+            return Err(CryptoError::InvalidKdfSettings(
+                "PBKDF2 iteration minimum is 5000",
+            ));
+        }
+
+        Ok(Kdf::Pbkdf2 {
+            iterations: non_zero(iterations),
+        })
+    }
+
+    /// Apply the same defaults and limits as the `CryptoService` in `libs/common`.
+    pub fn argon2id(
+        iterations: u32,
+        memory: Option<u32>,
+        parallelism: Option<u32>,
+    ) -> Result<Self, CryptoError> {
+        let memory = memory.unwrap_or(ARGON2_DEFAULT_MEMORY);
+        let parallelism = parallelism.unwrap_or(ARGON2_DEFAULT_PARALLELISM);
+
+        if iterations < ARGON2_MIN_ITERATIONS {
+            return Err(CryptoError::InvalidKdfSettings(
+                "Argon2 iteration minimum is 2",
+            ));
+        }
+        if memory < ARGON2_MIN_MEMORY {
+            return Err(CryptoError::InvalidKdfSettings(
+                "Argon2 memory minimum is 16 MB",
+            ));
+        }
+        if memory > ARGON2_MAX_MEMORY {
+            return Err(CryptoError::InvalidKdfSettings(
+                "Argon2 memory maximum is 1024 MB",
+            ));
+        }
+        if parallelism < ARGON2_MIN_PARALLELISM {
+            return Err(CryptoError::InvalidKdfSettings(
+                "Argon2 parallelism minimum is 1",
+            ));
+        }
+
+        Ok(Kdf::Argon2id {
+            iterations: non_zero(iterations),
+            memory: non_zero(memory),
+            parallelism: non_zero(parallelism),
+        })
+    }
+}
+
+/// Derive the 32 byte master key from the master password and the user's email.
+///
+/// This is CPU and memory intensive, callers on an async runtime should run it on a blocking
+/// thread.
+pub fn derive_master_key(
+    password: &[u8],
+    salt_email: &[u8],
+    kdf: &Kdf,
+) -> Result<Zeroizing<[u8; 32]>> {
+    let mut key = Zeroizing::new([0u8; 32]);
+
+    match kdf {
+        Kdf::Pbkdf2 { iterations } => {
+            pbkdf2::pbkdf2_hmac::<Sha256>(password, salt_email, iterations.get(), &mut *key);
+        }
+        Kdf::Argon2id {
+            iterations,
+            memory,
+            parallelism,
+        } => {
+            // Argon2 uses a hash of the salt, to ensure it is always long enough
+            let salt = Sha256::digest(salt_email);
+
+            let params = argon2::Params::new(
+                memory.get() * 1024, // Convert MiB to KiB
+                iterations.get(),
+                parallelism.get(),
+                Some(key.len()),
+            )
+            .map_err(|_| CryptoError::InvalidKdfSettings("Invalid Argon2 parameters"))?;
+
+            argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                .hash_password_into(password, &salt, &mut *key)
+                .map_err(|_| CryptoError::InvalidKdfSettings("Invalid Argon2 parameters"))?;
+        }
+    }
+
+    Ok(key)
+}
+
+/// Only call with values that have already been checked against the minimums.
+fn non_zero(value: u32) -> NonZeroU32 {
+    NonZeroU32::new(value).expect("value is validated to be non-zero")
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD as base64_engine, Engine};
+
+    use super::*;
+
+    #[test]
+    fn test_derive_master_key_pbkdf2() {
+        let kdf = Kdf::pbkdf2(5000).unwrap();
+        let key = derive_master_key(b"asdfasdf", b"test@bitwarden.com", &kdf).unwrap();
+
+        assert_eq!(
+            base64_engine.encode(key),
+            "V4MbVyn+ZgbZDHAQ9WrtveoqKKaRqd/A3kh1cnWKkPk="
+        );
+    }
+
+    #[test]
+    fn test_derive_master_key_argon2id() {
+        let kdf = Kdf::argon2id(2, Some(16), Some(1)).unwrap();
+        let key = derive_master_key(b"asdfasdf", b"test@bitwarden.com", &kdf).unwrap();
+
+        assert_eq!(
+            base64_engine.encode(key),
+            "Q7W9Qhi35rrqZ88UliwYVKBTYpw3g7+dZQ17o8fn8fw="
+        );
+    }
+
+    #[test]
+    fn test_derive_master_key_argon2id_defaults() {
+        let kdf = Kdf::argon2id(3, None, None).unwrap();
+        let key = derive_master_key(b"asdfasdf", b"test@bitwarden.com", &kdf).unwrap();
+
+        assert_eq!(
+            base64_engine.encode(key),
+            "t02WPMDt0VCIpAcFphhM9Zqh92xZC3fFDWgeMlSiK5M="
+        );
+    }
+
+    #[test]
+    fn test_pbkdf2_rejects_low_iterations() {
+        assert!(matches!(
+            Kdf::pbkdf2(4999),
+            Err(CryptoError::InvalidKdfSettings(_))
+        ));
+    }
+
+    #[test]
+    fn test_argon2_rejects_invalid_settings() {
+        for (iterations, memory, parallelism) in [
+            (1, None, None),
+            (3, Some(15), None),
+            (3, Some(1025), None),
+            (3, None, Some(0)),
+        ] {
+            assert!(matches!(
+                Kdf::argon2id(iterations, memory, parallelism),
+                Err(CryptoError::InvalidKdfSettings(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+};
+
+use zeroize::Zeroize;
+
+/// A heap allocated value that is zeroized when dropped. Where the OS allows it the memory is also
+/// locked, which keeps it out of swap and core dumps.
+///
+/// Every value gets pages of its own, surrounded by guard pages, so unlocking one value never
+/// unlocks another. Locking is best effort since it is subject to the memlock limit of the
+/// process. It is meant for small, long-lived secrets such as keys.
+pub struct Locked<T: AsMut<[u8]>> {
+    value: NonNull<T>,
+}
+
+// `Locked` owns its value like a `Box` does
+unsafe impl<T: AsMut<[u8]> + Send> Send for Locked<T> {}
+unsafe impl<T: AsMut<[u8]> + Sync> Sync for Locked<T> {}
+
+impl<T: AsMut<[u8]>> Locked<T> {
+    pub fn new(value: T) -> Self {
+        let ptr = unsafe { memsec::malloc::<T>() }.expect("Failed to allocate locked memory");
+        unsafe { ptr.as_ptr().write(value) };
+
+        Locked { value: ptr }
+    }
+}
+
+impl<T: AsMut<[u8]>> Deref for Locked<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.value.as_ref() }
+    }
+}
+
+impl<T: AsMut<[u8]>> DerefMut for Locked<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { self.value.as_mut() }
+    }
+}
+
+impl<T: AsMut<[u8]>> Zeroize for Locked<T> {
+    fn zeroize(&mut self) {
+        T::as_mut(self).zeroize();
+    }
+}
+
+impl<T: AsMut<[u8]>> Drop for Locked<T> {
+    fn drop(&mut self) {
+        self.zeroize();
+
+        unsafe {
+            ptr::drop_in_place(self.value.as_ptr());
+            memsec::free(self.value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use aes::cipher::{generic_array::GenericArray, typenum::U32};
+
+    use super::*;
+
+    // The smallest page size of the supported platforms
+    const PAGE_SIZE: usize = 4096;
+
+    fn page(locked: &Locked<GenericArray<u8, U32>>) -> usize {
+        &**locked as *const _ as usize / PAGE_SIZE
+    }
+
+    /// Reports the bytes it holds when it is dropped, which `Locked` does right before it frees
+    /// the memory.
+    struct DropProbe {
+        bytes: [u8; 32],
+        dropped: mpsc::Sender<[u8; 32]>,
+    }
+
+    impl AsMut<[u8]> for DropProbe {
+        fn as_mut(&mut self) -> &mut [u8] {
+            &mut self.bytes
+        }
+    }
+
+    impl Drop for DropProbe {
+        fn drop(&mut self) {
+            self.dropped.send(self.bytes).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_zeroize() {
+        let mut locked = Locked::new(GenericArray::<u8, U32>::from([0xABu8; 32]));
+        assert_eq!(locked.as_slice(), [0xABu8; 32]);
+
+        locked.zeroize();
+        assert_eq!(locked.as_slice(), [0u8; 32]);
+    }
+
+    #[test]
+    fn test_zeroize_on_drop() {
+        let (dropped, bytes) = mpsc::channel();
+        let locked = Locked::new(DropProbe {
+            bytes: [0xABu8; 32],
+            dropped,
+        });
+        assert_eq!(locked.bytes, [0xABu8; 32]);
+
+        drop(locked);
+        assert_eq!(bytes.recv().unwrap(), [0u8; 32]);
+    }
+
+    #[test]
+    fn test_separate_pages() {
+        let first = Locked::new(GenericArray::<u8, U32>::default());
+        let second = Locked::new(GenericArray::<u8, U32>::default());
+
+        assert_ne!(page(&first), page(&second));
+    }
+
+    #[test]
+    fn test_deref() {
+        let mut locked = Locked::new([1u8; 32]);
+        locked[0] = 2;
+
+        assert_eq!(locked[0], 2);
+        assert_eq!(locked[1..], [1u8; 31]);
+    }
+}
@@ -1,5 +1,15 @@
+pub use batch::decrypt_batch_to_utf8;
 pub use cipher_string::*;
 pub use crypto::*;
+pub use enc_array_buffer::{decrypt_file, encrypt_file};
+pub use kdf::*;
+pub use locked::*;
+pub use symmetric_crypto_key::*;
 
+mod batch;
 mod cipher_string;
 mod crypto;
+mod enc_array_buffer;
+mod kdf;
+mod locked;
+mod symmetric_crypto_key;
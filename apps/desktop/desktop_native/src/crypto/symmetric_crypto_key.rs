@@ -0,0 +1,178 @@
+use aes::cipher::{generic_array::GenericArray, typenum::U32};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use super::Locked;
+use crate::error::{CryptoError, Result};
+
+/// A symmetric key consisting of an encryption key and an optional MAC key.
+///
+/// Keys without a MAC key can only be used with `AesCbc256_B64`, keys with a MAC key are used
+/// with `AesCbc256_HmacSha256_B64`. The key bytes are held in [`Locked`] memory.
+pub struct SymmetricCryptoKey {
+    key: Locked<GenericArray<u8, U32>>,
+    mac_key: Option<Locked<GenericArray<u8, U32>>>,
+}
+
+// We manually implement these to make sure we don't print any sensitive data
+impl std::fmt::Debug for SymmetricCryptoKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SymmetricCryptoKey")
+            .field("type", &self.enc_type_name())
+            .finish()
+    }
+}
+
+impl TryFrom<&[u8]> for SymmetricCryptoKey {
+    type Error = CryptoError;
+
+    /// Accepts either a 32 byte encryption key, or a 64 byte key consisting of the encryption key
+    /// followed by the MAC key, such as the user key.
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        match value.len() {
+            32 => Ok(SymmetricCryptoKey {
+                key: locked_from_slice(value),
+                mac_key: None,
+            }),
+            64 => Ok(SymmetricCryptoKey {
+                key: locked_from_slice(&value[..32]),
+                mac_key: Some(locked_from_slice(&value[32..])),
+            }),
+            _ => Err(CryptoError::InvalidKey),
+        }
+    }
+}
+
+impl SymmetricCryptoKey {
+    pub fn new(key: GenericArray<u8, U32>, mac_key: Option<GenericArray<u8, U32>>) -> Self {
+        SymmetricCryptoKey {
+            key: Locked::new(key),
+            mac_key: mac_key.map(Locked::new),
+        }
+    }
+
+    /// Stretch a 32 byte key, such as the master key, into an encryption and MAC key using
+    /// HKDF-Expand with the `enc` and `mac` info labels.
+    pub fn stretch(key: &[u8; 32]) -> Result<Self> {
+        let hkdf = Hkdf::<Sha256>::from_prk(key).map_err(|_| CryptoError::InvalidKey)?;
+
+        let mut enc_key = Locked::new(GenericArray::default());
+        let mut mac_key = Locked::new(GenericArray::default());
+        hkdf.expand(b"enc", &mut enc_key)
+            .map_err(|_| CryptoError::InvalidKey)?;
+        hkdf.expand(b"mac", &mut mac_key)
+            .map_err(|_| CryptoError::InvalidKey)?;
+
+        Ok(SymmetricCryptoKey {
+            key: enc_key,
+            mac_key: Some(mac_key),
+        })
+    }
+
+    pub fn key(&self) -> &GenericArray<u8, U32> {
+        &self.key
+    }
+
+    pub fn mac_key(&self) -> Option<&GenericArray<u8, U32>> {
+        self.mac_key.as_deref()
+    }
+
+    /// The encryption type of the cipher strings produced by this key.
+    pub fn enc_type(&self) -> u8 {
+        match self.mac_key {
+            Some(_) => 2,
+            None => 0,
+        }
+    }
+
+    fn enc_type_name(&self) -> &str {
+        match self.enc_type() {
+            0 => "AesCbc256_B64",
+            2 => "AesCbc256_HmacSha256_B64",
+            _ => "Unknown",
+        }
+    }
+
+    /// The raw key, the encryption key followed by the MAC key if present.
+    pub fn to_vec(&self) -> Zeroizing<Vec<u8>> {
+        let mut key = Zeroizing::new(Vec::with_capacity(64));
+        key.extend_from_slice(&self.key);
+        if let Some(mac_key) = &self.mac_key {
+            key.extend_from_slice(mac_key);
+        }
+        key
+    }
+}
+
+fn locked_from_slice(value: &[u8]) -> Locked<GenericArray<u8, U32>> {
+    let mut key = Locked::new(GenericArray::default());
+    key.copy_from_slice(value);
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD as base64_engine, Engine};
+
+    use super::*;
+
+    #[test]
+    fn test_stretch() {
+        // Master key derived from "asdfasdf" and "test@bitwarden.com" with 5000 PBKDF2 iterations
+        let master_key = base64_engine
+            .decode("V4MbVyn+ZgbZDHAQ9WrtveoqKKaRqd/A3kh1cnWKkPk=")
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+        let key = SymmetricCryptoKey::stretch(&master_key).unwrap();
+
+        assert_eq!(key.enc_type(), 2);
+        assert_eq!(
+            base64_engine.encode(&*key.to_vec()),
+            "rpiZkitZzZyqVvgYdeBYUXpBsBmiPHkrp8VtC2/Yywg9xBxQQNM37sWP0/HV8dtDwRSSw6rXRpnpZmntipdg5Q=="
+        );
+    }
+
+    #[test]
+    fn test_from_user_key() {
+        let bytes: Vec<u8> = (0..64).collect();
+        let key = SymmetricCryptoKey::try_from(bytes.as_slice()).unwrap();
+
+        assert_eq!(key.enc_type(), 2);
+        assert_eq!(key.key().as_slice(), &bytes[..32]);
+        assert_eq!(key.mac_key().unwrap().as_slice(), &bytes[32..]);
+        assert_eq!(*key.to_vec(), bytes);
+    }
+
+    #[test]
+    fn test_from_enc_only_key() {
+        let bytes: Vec<u8> = (0..32).collect();
+        let key = SymmetricCryptoKey::try_from(bytes.as_slice()).unwrap();
+
+        assert_eq!(key.enc_type(), 0);
+        assert!(key.mac_key().is_none());
+        assert_eq!(*key.to_vec(), bytes);
+    }
+
+    #[test]
+    fn test_rejects_invalid_length() {
+        let bytes = [0u8; 48];
+        assert!(matches!(
+            SymmetricCryptoKey::try_from(bytes.as_slice()),
+            Err(CryptoError::InvalidKey)
+        ));
+    }
+
+    #[test]
+    fn test_debug_does_not_leak_key() {
+        let bytes = [0xABu8; 64];
+        let key = SymmetricCryptoKey::try_from(bytes.as_slice()).unwrap();
+
+        assert_eq!(
+            format!("{:?}", key),
+            "SymmetricCryptoKey { type: \"AesCbc256_HmacSha256_B64\" }"
+        );
+    }
+}
@@ -9,6 +9,9 @@ pub enum Error {
 
     #[error("Cryptography Error, {0}")]
     Crypto(#[from] CryptoError),
+
+    #[error("IO Error, {0}")]
+    Io(#[from] std::io::Error),
 }
 
 #[derive(Debug, Error)]
@@ -27,6 +30,42 @@ pub enum CSParseError {
 pub enum CryptoError {
     #[error("Error while decrypting cipher string")]
     KeyDecrypt,
+    #[error("Error while encrypting cipher string")]
+    KeyEncrypt,
+    #[error("The MAC of the cipher string does not match")]
+    InvalidMac,
+    #[error("The provided key is not valid")]
+    InvalidKey,
+    #[error("The encryption type is not supported by this operation")]
+    InvalidEncType,
+    #[error("Invalid KDF settings, {0}")]
+    InvalidKdfSettings(&'static str),
+    #[error("Error parsing encrypted ArrayBuffer: data is corrupted or has an invalid format")]
+    InvalidEncArrayBuffer,
+    #[error("The decrypted data is not valid UTF-8")]
+    InvalidUtf8,
+}
+
+/// Errors returned by the platform keychain. Every backend maps its native errors onto these
+/// variants, so callers can branch on the kind of failure rather than on the message.
+#[derive(Debug, Error)]
+pub enum KeychainError {
+    #[error("Password not found.")]
+    NotFound,
+    #[error("Access to the keychain was denied")]
+    AccessDenied,
+    #[error("The keychain is locked")]
+    Locked,
+    #[error("The keychain is not available, {0}")]
+    BackendUnavailable(String),
+    #[error("The keychain entry is invalid, {0}")]
+    InvalidData(String),
+    #[error("The keychain operation timed out")]
+    Timeout,
+    #[error("The keychain operation was cancelled")]
+    Cancelled,
+    #[error("Keychain error, {0}")]
+    Other(String),
 }
 
 // Ensure that the error messages implement Send and Sync
@@ -37,6 +76,8 @@ const _: () = {
     fn assert_all() {
         assert_send::<Error>();
         assert_sync::<Error>();
+        assert_send::<KeychainError>();
+        assert_sync::<KeychainError>();
     }
 };
 
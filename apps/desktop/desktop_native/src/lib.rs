@@ -8,41 +8,777 @@ mod password;
 
 #[napi]
 pub mod passwords {
+    use std::{
+        cell::Cell,
+        sync::Arc,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use napi::{
+        bindgen_prelude::{Buffer, FromNapiValue, ToNapiValue, TypeName},
+        Env, JsError, JsFunction, JsObject, Ref,
+    };
+    use tokio::sync::oneshot;
+    use zeroize::Zeroizing;
+
+    use super::{
+        crypto::SymmetricCryptoKey,
+        error::KeychainError,
+        password::{self, EncryptedFileStore, MemoryStore},
+    };
+
     /// Fetch the stored password from the keychain.
-    #[napi]
-    pub async fn get_password(service: String, account: String) -> napi::Result<String> {
-        super::password::get_password(&service, &account)
-            .map_err(|e| napi::Error::from_reason(e.to_string()))
+    #[napi(ts_return_type = "Promise<string>")]
+    pub fn get_password(
+        env: Env,
+        service: String,
+        account: String,
+        options: Option<KeychainOptions>,
+    ) -> napi::Result<JsObject> {
+        keychain_promise(env, options, move || {
+            password::get_password(&service, &account).map(|password| password.as_str().to_owned())
+        })
     }
 
     /// Fetch the stored password from the keychain that was stored with Keytar.
-    #[napi]
-    pub async fn get_password_keytar(service: String, account: String) -> napi::Result<String> {
-        super::password::get_password_keytar(&service, &account)
-            .map_err(|e| napi::Error::from_reason(e.to_string()))
+    #[napi(ts_return_type = "Promise<string>")]
+    pub fn get_password_keytar(
+        env: Env,
+        service: String,
+        account: String,
+        options: Option<KeychainOptions>,
+    ) -> napi::Result<JsObject> {
+        keychain_promise(env, options, move || {
+            password::get_password_keytar(&service, &account)
+                .map(|password| password.as_str().to_owned())
+        })
+    }
+
+    /// Fetch the stored password from the keychain along with its metadata, as far as the
+    /// platform keeps it.
+    #[napi(ts_return_type = "Promise<KeychainEntry>")]
+    pub fn get_entry(
+        env: Env,
+        service: String,
+        account: String,
+        options: Option<KeychainOptions>,
+    ) -> napi::Result<JsObject> {
+        keychain_promise(env, options, move || {
+            password::get_entry(&service, &account).map(|entry| KeychainEntry {
+                password: entry.password.as_str().to_owned(),
+                created: entry.created.and_then(to_millis),
+                modified: entry.modified.and_then(to_millis),
+                label: entry.label,
+                comment: entry.comment,
+            })
+        })
     }
 
     /// Save the password to the keychain. Adds an entry if none exists otherwise updates the existing entry.
-    #[napi]
-    pub async fn set_password(
+    /// The label and comment replace those of an existing entry, where the platform supports them.
+    /// Without metadata they are reset to the defaults.
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn set_password(
+        env: Env,
+        service: String,
+        account: String,
+        password: String,
+        metadata: Option<EntryMetadata>,
+        options: Option<KeychainOptions>,
+    ) -> napi::Result<JsObject> {
+        keychain_promise(env, options, move || match metadata {
+            Some(metadata) => password::set_password_with_metadata(
+                &service,
+                &account,
+                &password,
+                &password::EntryMetadata {
+                    label: metadata.label,
+                    comment: metadata.comment,
+                },
+            ),
+            None => password::set_password(&service, &account, &password),
+        })
+    }
+
+    /// Save the password to the keychain unless it is already stored, resolving to whether it was
+    /// written.
+    #[napi(ts_return_type = "Promise<boolean>")]
+    pub fn set_password_if_changed(
+        env: Env,
+        service: String,
+        account: String,
+        password: String,
+        options: Option<KeychainOptions>,
+    ) -> napi::Result<JsObject> {
+        keychain_promise(env, options, move || {
+            password::set_password_if_changed(&service, &account, &password)
+        })
+    }
+
+    /// Save the password to the keychain only if the stored password equals `expected`, where
+    /// `null` expects no entry to exist. Resolves to whether it was written.
+    #[napi(ts_return_type = "Promise<boolean>")]
+    pub fn compare_and_swap(
+        env: Env,
         service: String,
         account: String,
+        expected: Option<String>,
         password: String,
+        options: Option<KeychainOptions>,
+    ) -> napi::Result<JsObject> {
+        keychain_promise(env, options, move || {
+            password::compare_and_swap(&service, &account, expected.as_deref(), &password)
+        })
+    }
+
+    /// Fetch the stored secret from the keychain as raw bytes.
+    #[napi(ts_return_type = "Promise<Buffer>")]
+    pub fn get_secret_bytes(
+        env: Env,
+        service: String,
+        account: String,
+        options: Option<KeychainOptions>,
+    ) -> napi::Result<JsObject> {
+        keychain_promise(env, options, move || {
+            password::get_secret_bytes(&service, &account)
+                .map(|secret| Buffer::from(secret.to_vec()))
+        })
+    }
+
+    /// Save the secret to the keychain as raw bytes, so key material doesn't have to be encoded
+    /// as a string. Adds an entry if none exists otherwise updates the existing entry.
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn set_secret_bytes(
+        env: Env,
+        service: String,
+        account: String,
+        secret: Buffer,
+        options: Option<KeychainOptions>,
+    ) -> napi::Result<JsObject> {
+        let secret = Zeroizing::new(secret.to_vec());
+        keychain_promise(env, options, move || {
+            password::set_secret_bytes(&service, &account, &secret)
+        })
+    }
+
+    /// Delete the stored password from the keychain.
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn delete_password(
+        env: Env,
+        service: String,
+        account: String,
+        options: Option<KeychainOptions>,
+    ) -> napi::Result<JsObject> {
+        keychain_promise(env, options, move || {
+            password::delete_password(&service, &account)
+        })
+    }
+
+    /// List the accounts of every service that starts with the prefix, e.g. to find the entries of
+    /// logged out users. An empty prefix is rejected.
+    #[napi(ts_return_type = "Promise<Array<Account>>")]
+    pub fn list_accounts(
+        env: Env,
+        service_prefix: String,
+        options: Option<KeychainOptions>,
+    ) -> napi::Result<JsObject> {
+        keychain_promise(env, options, move || {
+            password::list_accounts(&service_prefix).map(|accounts| {
+                accounts
+                    .into_iter()
+                    .map(|entry| Account {
+                        service: entry.service,
+                        account: entry.account,
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+    }
+
+    /// Delete the passwords of every service that starts with the prefix, resolving to the number
+    /// of deleted entries. An empty prefix is rejected.
+    #[napi(ts_return_type = "Promise<number>")]
+    pub fn delete_all(
+        env: Env,
+        service_prefix: String,
+        options: Option<KeychainOptions>,
+    ) -> napi::Result<JsObject> {
+        keychain_promise(env, options, move || {
+            password::delete_all(&service_prefix).map(|deleted| deleted as u32)
+        })
+    }
+
+    #[napi(object)]
+    pub struct KeychainOptions {
+        /// Reject with a `Timeout` error when the operation takes longer, in milliseconds. The
+        /// keychain may be waiting for the user to unlock it.
+        pub timeout: Option<u32>,
+        /// Reject with a `Cancelled` error when the signal is aborted.
+        #[napi(ts_type = "AbortSignal")]
+        pub signal: Option<JsObject>,
+    }
+
+    #[napi(object)]
+    pub struct KeychainEntry {
+        pub password: String,
+        /// In milliseconds since the Unix epoch. Not recorded by Credential Manager on Windows.
+        pub created: Option<i64>,
+        /// In milliseconds since the Unix epoch.
+        pub modified: Option<i64>,
+        /// Not supported by Credential Manager on Windows.
+        pub label: Option<String>,
+        pub comment: Option<String>,
+    }
+
+    #[napi(object)]
+    pub struct EntryMetadata {
+        /// Shown by keychain managers instead of the service and account.
+        pub label: Option<String>,
+        pub comment: Option<String>,
+    }
+
+    #[napi(object)]
+    pub struct Account {
+        pub service: String,
+        pub account: String,
+    }
+
+    /// Select where the keychain functions store passwords. The encrypted file backend requires
+    /// the path of the file and a 64 byte key, the fallback backend generates its own key.
+    #[napi]
+    pub fn set_credential_backend(
+        backend: CredentialBackend,
+        path: Option<String>,
+        key: Option<Buffer>,
     ) -> napi::Result<()> {
-        super::password::set_password(&service, &account, &password)
+        let store: Arc<dyn password::CredentialStore> = match backend {
+            CredentialBackend::Os => password::default_store(),
+            CredentialBackend::Memory => Arc::new(MemoryStore::new()),
+            CredentialBackend::EncryptedFile => {
+                let (path, key) = path.zip(key).ok_or_else(|| {
+                    napi::Error::from_reason("The encrypted file backend requires a path and key")
+                })?;
+                let key = SymmetricCryptoKey::try_from(key.as_ref())
+                    .map_err(|e| napi::Error::from_reason(e.to_string()))?;
+
+                Arc::new(
+                    EncryptedFileStore::new(path, key)
+                        .map_err(|e| napi::Error::from_reason(e.to_string()))?,
+                )
+            }
+            #[cfg(target_os = "linux")]
+            CredentialBackend::Fallback => Arc::new(password::SecretServiceStore::fallback()),
+            #[cfg(not(target_os = "linux"))]
+            CredentialBackend::Fallback => {
+                return Err(napi::Error::from_reason(
+                    "The fallback backend is only supported on Linux",
+                ))
+            }
+        };
+
+        password::set_store(store);
+        Ok(())
+    }
+
+    #[napi]
+    pub enum CredentialBackend {
+        /// libsecret on Linux, Keychain on macOS and Credential Manager on Windows.
+        Os = 0,
+        /// Kept in memory only, nothing is persisted.
+        Memory = 1,
+        /// A single encrypted file.
+        EncryptedFile = 2,
+        /// The encrypted file under `$XDG_DATA_HOME` that Linux uses when no Secret Service
+        /// provider is running.
+        Fallback = 3,
+    }
+
+    /// Whether passwords are currently stored in the Linux fallback file instead of the
+    /// keychain, either because it was selected or because no Secret Service provider is running.
+    #[napi]
+    pub fn uses_fallback_store() -> bool {
+        password::store().uses_fallback()
+    }
+
+    /// The `code` of the errors thrown by the keychain functions, stable across platforms.
+    #[napi(string_enum)]
+    pub enum KeychainErrorCode {
+        NotFound,
+        AccessDenied,
+        Locked,
+        BackendUnavailable,
+        InvalidData,
+        Timeout,
+        Cancelled,
+        Other,
+    }
+
+    impl From<&KeychainError> for KeychainErrorCode {
+        fn from(error: &KeychainError) -> Self {
+            match error {
+                KeychainError::NotFound => KeychainErrorCode::NotFound,
+                KeychainError::AccessDenied => KeychainErrorCode::AccessDenied,
+                KeychainError::Locked => KeychainErrorCode::Locked,
+                KeychainError::BackendUnavailable(_) => KeychainErrorCode::BackendUnavailable,
+                KeychainError::InvalidData(_) => KeychainErrorCode::InvalidData,
+                KeychainError::Timeout => KeychainErrorCode::Timeout,
+                KeychainError::Cancelled => KeychainErrorCode::Cancelled,
+                KeychainError::Other(_) => KeychainErrorCode::Other,
+            }
+        }
+    }
+
+    impl AsRef<str> for KeychainErrorCode {
+        fn as_ref(&self) -> &str {
+            match self {
+                KeychainErrorCode::NotFound => "NotFound",
+                KeychainErrorCode::AccessDenied => "AccessDenied",
+                KeychainErrorCode::Locked => "Locked",
+                KeychainErrorCode::BackendUnavailable => "BackendUnavailable",
+                KeychainErrorCode::InvalidData => "InvalidData",
+                KeychainErrorCode::Timeout => "Timeout",
+                KeychainErrorCode::Cancelled => "Cancelled",
+                KeychainErrorCode::Other => "Other",
+            }
+        }
+    }
+
+    fn to_millis(time: SystemTime) -> Option<i64> {
+        let millis = time.duration_since(UNIX_EPOCH).ok()?.as_millis();
+        i64::try_from(millis).ok()
+    }
+
+    /// Run the keychain operation on the blocking pool and return a promise for its result. Async
+    /// napi functions can only reject with a `Status` code, so the JS error is built here with the
+    /// `KeychainErrorCode` as its `code`.
+    fn keychain_promise<T, F>(
+        env: Env,
+        options: Option<KeychainOptions>,
+        operation: F,
+    ) -> napi::Result<JsObject>
+    where
+        T: ToNapiValue + TypeName + Send + 'static,
+        F: FnOnce() -> Result<T, KeychainError> + Send + 'static,
+    {
+        let (timeout, signal) = options
+            .map(|options| (options.timeout, options.signal))
+            .unwrap_or_default();
+        let timeout = timeout.map(|timeout| Duration::from_millis(timeout.into()));
+        let (aborted, listener) = match signal {
+            Some(signal) => {
+                let (aborted, listener) = on_abort(&env, signal)?;
+                (Some(aborted), listener)
+            }
+            None => (None, None),
+        };
+
+        let cancelled = async move {
+            // A dropped sender means the signal can't be aborted anymore
+            if let Some(aborted) = aborted {
+                if aborted.await.is_ok() {
+                    return;
+                }
+            }
+            std::future::pending().await
+        };
+
+        env.execute_tokio_future(
+            async move { Ok(password::run_blocking(operation, timeout, cancelled).await) },
+            move |env, result| {
+                if let Some(listener) = listener {
+                    let _ = listener.remove(env);
+                }
+                result.map_err(|e| {
+                    let error = napi::Error::new(KeychainErrorCode::from(&e), e.to_string());
+                    napi::Error::from(JsError::from(error).into_unknown(*env))
+                })
+            },
+        )
+    }
+
+    // Completes when the `AbortSignal` is aborted. Unless it already is, this also returns the
+    // listener to remove once the operation settles.
+    fn on_abort(
+        env: &Env,
+        signal: JsObject,
+    ) -> napi::Result<(oneshot::Receiver<()>, Option<AbortListener>)> {
+        let (sender, receiver) = oneshot::channel();
+        if signal.get_named_property::<bool>("aborted")? {
+            let _ = sender.send(());
+            return Ok((receiver, None));
+        }
+
+        let sender = Cell::new(Some(sender));
+        let listener = env.create_function_from_closure("onabort", move |_| {
+            if let Some(sender) = sender.take() {
+                let _ = sender.send(());
+            }
+            Ok(())
+        })?;
+
+        let abort_listener = AbortListener {
+            signal: env.create_reference(&signal)?,
+            listener: env.create_reference(&listener)?,
+        };
+
+        let add_event_listener: JsFunction = signal.get_named_property("addEventListener")?;
+        let added = add_event_listener.call(
+            Some(&signal),
+            &[
+                env.create_string("abort")?.into_unknown(),
+                listener.into_unknown(),
+            ],
+        );
+        match added {
+            Ok(_) => Ok((receiver, Some(abort_listener))),
+            Err(e) => {
+                abort_listener.release(env)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// An `abort` listener added to an `AbortSignal`, which is removed again so long-lived signals
+    /// don't collect a listener for every operation.
+    struct AbortListener {
+        signal: Ref<()>,
+        listener: Ref<()>,
+    }
+
+    impl AbortListener {
+        fn remove(self, env: &Env) -> napi::Result<()> {
+            let removed = (|| {
+                let signal: JsObject = env.get_reference_value(&self.signal)?;
+                let listener: JsFunction = env.get_reference_value(&self.listener)?;
+                let remove_event_listener: JsFunction =
+                    signal.get_named_property("removeEventListener")?;
+                remove_event_listener.call(
+                    Some(&signal),
+                    &[
+                        env.create_string("abort")?.into_unknown(),
+                        listener.into_unknown(),
+                    ],
+                )
+            })();
+
+            self.release(env)?;
+            removed.map(|_| ())
+        }
+
+        fn release(mut self, env: &Env) -> napi::Result<()> {
+            self.signal.unref(*env)?;
+            self.listener.unref(*env)?;
+            Ok(())
+        }
+    }
+}
+
+#[napi(js_name = "crypto")]
+pub mod crypto_bindings {
+    use std::{path::PathBuf, sync::Arc};
+
+    use napi::{
+        bindgen_prelude::{AsyncTask, Buffer, FromNapiValue, ToNapiValue},
+        threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode},
+        Env, JsFunction, JsUndefined, Task,
+    };
+    use zeroize::Zeroizing;
+
+    use super::crypto::{self, CipherString, SymmetricCryptoKey};
+
+    /// Parse a CipherString into its encryption type and decoded parts.
+    #[napi]
+    pub fn parse_cipher_string(cipher_string: String) -> napi::Result<ParsedCipherString> {
+        let cipher: CipherString = cipher_string
+            .parse()
+            .map_err(|e: super::error::Error| napi::Error::from_reason(e.to_string()))?;
+
+        let enc_type = cipher.enc_type().into();
+        let (iv, mac, data) = match cipher {
+            CipherString::AesCbc256_B64 { iv, data } => (Some(iv.to_vec()), None, data),
+            CipherString::AesCbc128_HmacSha256_B64 { iv, mac, data }
+            | CipherString::AesCbc256_HmacSha256_B64 { iv, mac, data } => {
+                (Some(iv.to_vec()), Some(mac.to_vec()), data)
+            }
+            CipherString::Rsa2048_OaepSha256_B64 { data }
+            | CipherString::Rsa2048_OaepSha1_B64 { data } => (None, None, data),
+            CipherString::Rsa2048_OaepSha256_HmacSha256_B64 { mac, data }
+            | CipherString::Rsa2048_OaepSha1_HmacSha256_B64 { mac, data } => {
+                (None, Some(mac.to_vec()), data)
+            }
+        };
+
+        Ok(ParsedCipherString {
+            enc_type,
+            iv: iv.map(Buffer::from),
+            mac: mac.map(Buffer::from),
+            data: data.into(),
+        })
+    }
+
+    /// Check whether the string is a well formed CipherString.
+    #[napi]
+    pub fn is_valid_cipher_string(cipher_string: String) -> bool {
+        cipher_string.parse::<CipherString>().is_ok()
+    }
+
+    /// Encrypt the data with a random IV. A 32 byte key produces a type 0 CipherString, a 64 byte
+    /// key (encryption key followed by the MAC key) produces a type 2 CipherString.
+    #[napi]
+    pub async fn encrypt(data: Buffer, key: Buffer) -> napi::Result<String> {
+        let key = SymmetricCryptoKey::try_from(key.as_ref())
+            .map_err(|e| napi::Error::from_reason(e.to_string()))?;
+
+        crypto::encrypt(&data, &key)
+            .map(|cipher| cipher.to_string())
             .map_err(|e| napi::Error::from_reason(e.to_string()))
     }
 
-    /// Delete the stored password from the keychain.
+    /// Decrypt a type 0 or type 2 CipherString. The key follows the same layout as in `encrypt`.
+    #[napi]
+    pub async fn decrypt(cipher_string: String, key: Buffer) -> napi::Result<Buffer> {
+        let key = SymmetricCryptoKey::try_from(key.as_ref())
+            .map_err(|e| napi::Error::from_reason(e.to_string()))?;
+
+        cipher_string
+            .parse::<CipherString>()
+            .and_then(|cipher| crypto::decrypt(&cipher, &key))
+            .map(|decrypted| decrypted.to_vec().into())
+            .map_err(|e| napi::Error::from_reason(e.to_string()))
+    }
+
+    /// Decrypt many type 0 or type 2 CipherStrings to UTF-8 strings in parallel. Each item gets its
+    /// own result, so a single corrupt CipherString does not fail the whole batch.
+    #[napi]
+    pub async fn decrypt_batch(
+        cipher_strings: Vec<String>,
+        key: Buffer,
+    ) -> napi::Result<Vec<DecryptResult>> {
+        let key = SymmetricCryptoKey::try_from(key.as_ref())
+            .map_err(|e| napi::Error::from_reason(e.to_string()))?;
+
+        let results = crypto::decrypt_batch_to_utf8(cipher_strings, Arc::new(key)).await;
+
+        Ok(results
+            .into_iter()
+            .map(|result| match result {
+                Ok(data) => DecryptResult {
+                    data: Some(data),
+                    error: None,
+                },
+                Err(e) => DecryptResult {
+                    data: None,
+                    error: Some(e.to_string()),
+                },
+            })
+            .collect())
+    }
+
+    /// Encrypt the data with a DER encoded SPKI public key, producing a type 4 CipherString.
+    #[napi]
+    pub async fn encrypt_rsa(data: Buffer, public_key: Buffer) -> napi::Result<String> {
+        crypto::encrypt_rsa2048_oaep_sha1(&data, &public_key)
+            .map(|cipher| cipher.to_string())
+            .map_err(|e| napi::Error::from_reason(e.to_string()))
+    }
+
+    /// Decrypt a RSA CipherString with a DER encoded PKCS#8 private key.
+    #[napi]
+    pub async fn decrypt_rsa(cipher_string: String, private_key: Buffer) -> napi::Result<Buffer> {
+        cipher_string
+            .parse::<CipherString>()
+            .and_then(|cipher| crypto::decrypt_rsa2048_oaep(&cipher, &private_key))
+            .map(|decrypted| decrypted.to_vec().into())
+            .map_err(|e| napi::Error::from_reason(e.to_string()))
+    }
+
+    /// Derive the master key from the master password. Runs on a blocking thread since the KDF
+    /// is deliberately slow.
     #[napi]
-    pub async fn delete_password(service: String, account: String) -> napi::Result<()> {
-        super::password::delete_password(&service, &account)
+    pub async fn derive_master_key(
+        password: String,
+        salt_email: String,
+        kdf_config: KdfConfig,
+    ) -> napi::Result<Buffer> {
+        let kdf = crypto::Kdf::try_from(&kdf_config)
+            .map_err(|e| napi::Error::from_reason(e.to_string()))?;
+
+        let password = Zeroizing::new(password);
+        let key = tokio::task::spawn_blocking(move || {
+            crypto::derive_master_key(password.as_bytes(), salt_email.as_bytes(), &kdf)
+        })
+        .await
+        .map_err(|e| napi::Error::from_reason(e.to_string()))?
+        .map_err(|e| napi::Error::from_reason(e.to_string()))?;
+
+        Ok(key.to_vec().into())
+    }
+
+    /// Encrypt the file at `input_path` into `output_path` in the `EncArrayBuffer` format, used for
+    /// attachments and file Sends. The key follows the same layout as in `encrypt`.
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn encrypt_file(
+        input_path: String,
+        output_path: String,
+        key: Buffer,
+        #[napi(ts_arg_type = "(processed: number, total: number) => void")] progress: Option<
+            JsFunction,
+        >,
+    ) -> napi::Result<AsyncTask<FileTask>> {
+        FileTask::new(
+            FileOperation::Encrypt,
+            input_path,
+            output_path,
+            key,
+            progress,
+        )
+    }
+
+    /// Decrypt an `EncArrayBuffer` file at `input_path` into `output_path`. The MAC is verified
+    /// before any plaintext is written, and an existing output is only replaced once decryption
+    /// succeeded. The input can't be the output.
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn decrypt_file(
+        input_path: String,
+        output_path: String,
+        key: Buffer,
+        #[napi(ts_arg_type = "(processed: number, total: number) => void")] progress: Option<
+            JsFunction,
+        >,
+    ) -> napi::Result<AsyncTask<FileTask>> {
+        FileTask::new(
+            FileOperation::Decrypt,
+            input_path,
+            output_path,
+            key,
+            progress,
+        )
+    }
+
+    enum FileOperation {
+        Encrypt,
+        Decrypt,
+    }
+
+    /// Runs the file encryption on the libuv thread pool, reporting progress back to the JS thread.
+    pub struct FileTask {
+        operation: FileOperation,
+        input_path: PathBuf,
+        output_path: PathBuf,
+        key: SymmetricCryptoKey,
+        progress: Option<ThreadsafeFunction<(u64, u64), ErrorStrategy::Fatal>>,
+    }
+
+    impl FileTask {
+        fn new(
+            operation: FileOperation,
+            input_path: String,
+            output_path: String,
+            key: Buffer,
+            progress: Option<JsFunction>,
+        ) -> napi::Result<AsyncTask<Self>> {
+            let key = SymmetricCryptoKey::try_from(key.as_ref())
+                .map_err(|e| napi::Error::from_reason(e.to_string()))?;
+
+            let progress = progress
+                .map(|callback| {
+                    callback.create_threadsafe_function(0, |ctx| {
+                        let (processed, total): (u64, u64) = ctx.value;
+                        Ok(vec![
+                            ctx.env.create_double(processed as f64)?,
+                            ctx.env.create_double(total as f64)?,
+                        ])
+                    })
+                })
+                .transpose()?;
+
+            Ok(AsyncTask::new(FileTask {
+                operation,
+                input_path: input_path.into(),
+                output_path: output_path.into(),
+                key,
+                progress,
+            }))
+        }
+    }
+
+    impl Task for FileTask {
+        type Output = ();
+        type JsValue = JsUndefined;
+
+        fn compute(&mut self) -> napi::Result<Self::Output> {
+            let progress = |processed, total| {
+                if let Some(progress) = &self.progress {
+                    progress.call((processed, total), ThreadsafeFunctionCallMode::NonBlocking);
+                }
+            };
+
+            match self.operation {
+                FileOperation::Encrypt => {
+                    crypto::encrypt_file(&self.input_path, &self.output_path, &self.key, progress)
+                }
+                FileOperation::Decrypt => {
+                    crypto::decrypt_file(&self.input_path, &self.output_path, &self.key, progress)
+                }
+            }
             .map_err(|e| napi::Error::from_reason(e.to_string()))
+        }
+
+        fn resolve(&mut self, env: Env, _output: Self::Output) -> napi::Result<Self::JsValue> {
+            env.get_undefined()
+        }
+    }
+
+    /// Mirrors `KdfType` in `libs/common`.
+    #[napi]
+    pub enum KdfType {
+        Pbkdf2Sha256 = 0,
+        Argon2id = 1,
+    }
+
+    /// Mirrors `KdfConfig` in `libs/common`. Memory is in MiB.
+    #[napi(object)]
+    pub struct KdfConfig {
+        pub kdf_type: KdfType,
+        pub iterations: u32,
+        pub memory: Option<u32>,
+        pub parallelism: Option<u32>,
+    }
+
+    impl TryFrom<&KdfConfig> for crypto::Kdf {
+        type Error = crate::error::CryptoError;
+
+        fn try_from(config: &KdfConfig) -> Result<Self, Self::Error> {
+            match config.kdf_type {
+                KdfType::Pbkdf2Sha256 => crypto::Kdf::pbkdf2(config.iterations),
+                KdfType::Argon2id => {
+                    crypto::Kdf::argon2id(config.iterations, config.memory, config.parallelism)
+                }
+            }
+        }
+    }
+
+    /// Result of a single item in `decrypt_batch`, exactly one of the fields is set.
+    #[napi(object)]
+    pub struct DecryptResult {
+        pub data: Option<String>,
+        pub error: Option<String>,
+    }
+
+    #[napi(object)]
+    pub struct ParsedCipherString {
+        pub enc_type: u32,
+        pub iv: Option<Buffer>,
+        pub mac: Option<Buffer>,
+        pub data: Buffer,
     }
 }
 
 #[napi]
 pub mod biometrics {
+    use napi::bindgen_prelude::{FromNapiValue, ToNapiValue};
+    use zeroize::Zeroize;
+
     use super::biometric::{Biometric, BiometricTrait};
 
     // Prompt for biometric confirmation
@@ -50,13 +786,21 @@ pub mod biometrics {
     pub async fn prompt(
         hwnd: napi::bindgen_prelude::Buffer,
         message: String,
-    ) -> napi::Result<bool> {
-        Biometric::prompt(hwnd.into(), message).map_err(|e| napi::Error::from_reason(e.to_string()))
+    ) -> napi::Result<PromptOutcome> {
+        // The prompt blocks until the user responds, so keep it off the async runtime
+        let hwnd: Vec<u8> = hwnd.into();
+        tokio::task::spawn_blocking(move || Biometric::prompt(hwnd, message))
+            .await
+            .map_err(|e| napi::Error::from_reason(e.to_string()))?
+            .map_err(|e| napi::Error::from_reason(e.to_string()))
     }
 
     #[napi]
-    pub async fn available() -> napi::Result<bool> {
-        Biometric::available().map_err(|e| napi::Error::from_reason(e.to_string()))
+    pub async fn available() -> napi::Result<BiometricStatus> {
+        tokio::task::spawn_blocking(Biometric::available)
+            .await
+            .map_err(|e| napi::Error::from_reason(e.to_string()))?
+            .map_err(|e| napi::Error::from_reason(e.to_string()))
     }
 
     #[napi]
@@ -76,8 +820,9 @@ pub mod biometrics {
         service: String,
         account: String,
         key_material: Option<KeyMaterial>,
+        options: Option<BiometricSecretOptions>,
     ) -> napi::Result<String> {
-        let result = Biometric::get_biometric_secret(&service, &account, key_material)
+        let result = Biometric::get_biometric_secret(&service, &account, key_material, options)
             .map_err(|e| napi::Error::from_reason(e.to_string()));
         result
     }
@@ -95,15 +840,82 @@ pub mod biometrics {
             .map_err(|e| napi::Error::from_reason(e.to_string()))
     }
 
-    #[napi(object)]
+    #[napi(object, object_to_js = false)]
     pub struct KeyMaterial {
         pub os_key_part_b64: String,
         pub client_key_part_b64: Option<String>,
     }
 
+    impl Drop for KeyMaterial {
+        fn drop(&mut self) {
+            self.os_key_part_b64.zeroize();
+            self.client_key_part_b64.zeroize();
+        }
+    }
+
+    #[napi(object, object_to_js = false)]
+    #[derive(Default)]
+    pub struct BiometricSecretOptions {
+        /// Refuse secrets stored unencrypted, rather than returning them.
+        pub strict: Option<bool>,
+        /// The challenge the key material was derived from. Secrets stored unencrypted are only
+        /// encrypted when read if it's provided.
+        pub iv_b64: Option<String>,
+    }
+
     #[napi(object)]
     pub struct OsDerivedKey {
         pub key_b64: String,
         pub iv_b64: String,
     }
+
+    /// How a biometric prompt ended.
+    #[napi(string_enum)]
+    #[derive(Debug, PartialEq)]
+    pub enum PromptOutcome {
+        Verified,
+        /// The user dismissed the prompt.
+        Canceled,
+        /// The user failed to verify too many times.
+        RetriesExhausted,
+        /// The device is in use, e.g. by another prompt.
+        DeviceBusy,
+        /// The device is present, but the user hasn't set it up.
+        NotConfigured,
+        /// There is no device to verify with, or it's disabled.
+        Unavailable,
+    }
+
+    /// Why biometrics are available or not.
+    #[napi(string_enum)]
+    #[derive(Debug, PartialEq)]
+    pub enum BiometricStatusReason {
+        Available,
+        /// The device is in use, but can prompt once it's released.
+        DeviceBusy,
+        DeviceNotPresent,
+        /// The device is present, but the user hasn't set it up.
+        NotConfigured,
+        DisabledByPolicy,
+        /// The platform has no biometrics support.
+        Unsupported,
+    }
+
+    #[napi(object)]
+    pub struct BiometricStatus {
+        pub available: bool,
+        pub reason: BiometricStatusReason,
+    }
+
+    impl From<BiometricStatusReason> for BiometricStatus {
+        fn from(reason: BiometricStatusReason) -> Self {
+            Self {
+                available: matches!(
+                    reason,
+                    BiometricStatusReason::Available | BiometricStatusReason::DeviceBusy
+                ),
+                reason,
+            }
+        }
+    }
 }
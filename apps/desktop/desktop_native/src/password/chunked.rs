@@ -0,0 +1,551 @@
+use rand::Rng;
+use zeroize::Zeroizing;
+
+use super::{password_from_bytes, Account, CredentialStore, Entry, EntryMetadata, Result};
+use crate::error::KeychainError;
+
+// Stored in place of a secret that was split, followed by `<generation>:<chunks>:<length>`
+const MANIFEST_PREFIX: &str = "bitwarden-chunked-v1:";
+const CHUNK_SEPARATOR: &str = "#chunk-";
+
+/// Splits secrets larger than the backend allows over several entries.
+///
+/// The chunks are stored under the same service as `<account>#chunk-<generation>-<n>`, and the
+/// entry itself holds a manifest with the generation, the number of chunks and the total length.
+/// Chunk entries are hidden from [`CredentialStore::list_accounts`] and deleted together with their
+/// manifest.
+///
+/// Every write stores its chunks under a new generation before it replaces the manifest, and only
+/// then deletes the chunks of earlier generations. A write that fails part way leaves the previous
+/// secret intact, and its chunks are removed by the next chunked write or the delete. Writes that
+/// neither replace a manifest nor are chunked themselves don't look for chunks, since that lists
+/// every entry of the backend.
+pub struct ChunkedStore<S> {
+    inner: S,
+    max_size: usize,
+}
+
+impl<S: CredentialStore> ChunkedStore<S> {
+    /// Wrap `inner`, which accepts secrets of at most `max_size` bytes.
+    pub fn new(inner: S, max_size: usize) -> Self {
+        // The prefix followed by a `u32`, two `usize` and their separators
+        assert!(
+            max_size > MANIFEST_PREFIX.len() + 10 + 2 * 20 + 2,
+            "the manifest has to fit in a single entry"
+        );
+        Self { inner, max_size }
+    }
+
+    // Store the secret in chunks of a new generation, returning the manifest that refers to them
+    fn write_chunks(&self, service: &str, account: &str, secret: &[u8]) -> Result<(u32, String)> {
+        let generation = rand::thread_rng().gen();
+        let mut chunks = 0;
+        for (index, chunk) in secret.chunks(self.max_size).enumerate() {
+            self.inner.set_secret_bytes(
+                service,
+                &chunk_account(account, generation, index),
+                chunk,
+            )?;
+            chunks += 1;
+        }
+        Ok((generation, manifest(generation, chunks, secret.len())))
+    }
+
+    fn read_chunks(
+        &self,
+        service: &str,
+        account: &str,
+        manifest: &[u8],
+    ) -> Result<Option<Zeroizing<Vec<u8>>>> {
+        let Some(manifest) = manifest.strip_prefix(MANIFEST_PREFIX.as_bytes()) else {
+            return Ok(None);
+        };
+        let (generation, chunks, length) = parse_manifest(manifest).ok_or_else(|| {
+            KeychainError::InvalidData("the chunk manifest is malformed".to_owned())
+        })?;
+
+        let mut secret = Zeroizing::new(Vec::with_capacity(length));
+        for index in 0..chunks {
+            match self
+                .inner
+                .get_secret_bytes(service, &chunk_account(account, generation, index))
+            {
+                Ok(chunk) => secret.extend_from_slice(&chunk),
+                Err(KeychainError::NotFound) => {
+                    return Err(KeychainError::InvalidData(format!(
+                        "chunk {} of {} is missing",
+                        index, chunks
+                    )))
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        if secret.len() != length {
+            return Err(KeychainError::InvalidData(
+                "the chunks don't match the manifest".to_owned(),
+            ));
+        }
+        Ok(Some(secret))
+    }
+
+    // Whether the current value of the entry is a manifest, going by `is_manifest` on the result of
+    // reading it. A value that can't be read, e.g. when it was written as the other type, may be
+    fn replaces_manifest(is_manifest: Result<bool>) -> bool {
+        match is_manifest {
+            Ok(is_manifest) => is_manifest,
+            Err(KeychainError::NotFound) => false,
+            Err(_) => true,
+        }
+    }
+
+    // Delete the chunks of the account other than those of the `keep` generation, which also
+    // removes chunks left behind by an interrupted write
+    fn delete_chunks(&self, service: &str, account: &str, keep: Option<u32>) -> Result<()> {
+        for entry in self.inner.list_accounts(service)? {
+            if entry.service != service {
+                continue;
+            }
+            match parse_chunk_account(account, &entry.account) {
+                Some((generation, _)) if Some(generation) != keep => {
+                    match self.inner.delete_password(service, &entry.account) {
+                        Ok(()) | Err(KeychainError::NotFound) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: CredentialStore> CredentialStore for ChunkedStore<S> {
+    fn get_password(&self, service: &str, account: &str) -> Result<Zeroizing<String>> {
+        let password = self.inner.get_password(service, account)?;
+        match self.read_chunks(service, account, password.as_bytes())? {
+            Some(secret) => password_from_bytes(secret),
+            None => Ok(password),
+        }
+    }
+
+    fn set_password(&self, service: &str, account: &str, password: &str) -> Result<()> {
+        self.set_password_with_metadata(service, account, password, &EntryMetadata::default())
+    }
+
+    fn get_entry(&self, service: &str, account: &str) -> Result<Entry> {
+        let mut entry = self.inner.get_entry(service, account)?;
+        if let Some(secret) = self.read_chunks(service, account, entry.password.as_bytes())? {
+            entry.password = password_from_bytes(secret)?;
+        }
+        Ok(entry)
+    }
+
+    fn set_password_with_metadata(
+        &self,
+        service: &str,
+        account: &str,
+        password: &str,
+        metadata: &EntryMetadata,
+    ) -> Result<()> {
+        // Backends may store passwords as UTF-16, which takes at most two bytes per UTF-8 byte.
+        // Secrets that look like a manifest are always split so they can't be mistaken for one
+        if password.len() * 2 <= self.max_size && !password.starts_with(MANIFEST_PREFIX) {
+            let replaces_manifest = Self::replaces_manifest(
+                self.inner
+                    .get_password(service, account)
+                    .map(|current| current.starts_with(MANIFEST_PREFIX)),
+            );
+            self.inner
+                .set_password_with_metadata(service, account, password, metadata)?;
+            if replaces_manifest {
+                self.delete_chunks(service, account, None)?;
+            }
+            return Ok(());
+        }
+
+        // The metadata is kept on the manifest
+        let (generation, manifest) = self.write_chunks(service, account, password.as_bytes())?;
+        self.inner
+            .set_password_with_metadata(service, account, &manifest, metadata)?;
+        self.delete_chunks(service, account, Some(generation))
+    }
+
+    fn get_secret_bytes(&self, service: &str, account: &str) -> Result<Zeroizing<Vec<u8>>> {
+        let secret = self.inner.get_secret_bytes(service, account)?;
+        match self.read_chunks(service, account, &secret)? {
+            Some(secret) => Ok(secret),
+            None => Ok(secret),
+        }
+    }
+
+    fn set_secret_bytes(&self, service: &str, account: &str, secret: &[u8]) -> Result<()> {
+        if secret.len() <= self.max_size && !secret.starts_with(MANIFEST_PREFIX.as_bytes()) {
+            let replaces_manifest = Self::replaces_manifest(
+                self.inner
+                    .get_secret_bytes(service, account)
+                    .map(|current| current.starts_with(MANIFEST_PREFIX.as_bytes())),
+            );
+            self.inner.set_secret_bytes(service, account, secret)?;
+            if replaces_manifest {
+                self.delete_chunks(service, account, None)?;
+            }
+            return Ok(());
+        }
+
+        let (generation, manifest) = self.write_chunks(service, account, secret)?;
+        self.inner
+            .set_secret_bytes(service, account, manifest.as_bytes())?;
+        self.delete_chunks(service, account, Some(generation))
+    }
+
+    fn delete_password(&self, service: &str, account: &str) -> Result<()> {
+        // Remove orphaned chunks even when the manifest is gone
+        let result = self.inner.delete_password(service, account);
+        self.delete_chunks(service, account, None)?;
+        result
+    }
+
+    fn list_accounts(&self, service_prefix: &str) -> Result<Vec<Account>> {
+        let mut accounts = self.inner.list_accounts(service_prefix)?;
+        accounts.retain(|entry| !is_chunk_account(&entry.account));
+        Ok(accounts)
+    }
+
+    fn uses_fallback(&self) -> bool {
+        self.inner.uses_fallback()
+    }
+}
+
+fn chunk_account(account: &str, generation: u32, index: usize) -> String {
+    format!("{}{}{}-{}", account, CHUNK_SEPARATOR, generation, index)
+}
+
+fn is_chunk_account(account: &str) -> bool {
+    match account.rsplit_once(CHUNK_SEPARATOR) {
+        Some((_, suffix)) => parse_chunk_suffix(suffix).is_some(),
+        None => false,
+    }
+}
+
+// The generation and index of a chunk of `account`
+fn parse_chunk_account(account: &str, chunk_account: &str) -> Option<(u32, usize)> {
+    let suffix = chunk_account
+        .strip_prefix(account)?
+        .strip_prefix(CHUNK_SEPARATOR)?;
+    parse_chunk_suffix(suffix)
+}
+
+fn parse_chunk_suffix(suffix: &str) -> Option<(u32, usize)> {
+    let (generation, index) = suffix.split_once('-')?;
+    Some((parse_digits(generation)?, parse_digits(index)?))
+}
+
+// Unlike `str::parse`, doesn't accept a sign
+fn parse_digits<T: std::str::FromStr>(digits: &str) -> Option<T> {
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn manifest(generation: u32, chunks: usize, length: usize) -> String {
+    format!("{}{}:{}:{}", MANIFEST_PREFIX, generation, chunks, length)
+}
+
+fn parse_manifest(manifest: &[u8]) -> Option<(u32, usize, usize)> {
+    let mut parts = std::str::from_utf8(manifest).ok()?.splitn(3, ':');
+    Some((
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::password::MemoryStore;
+
+    const MAX_SIZE: usize = 80;
+
+    fn store() -> ChunkedStore<MemoryStore> {
+        ChunkedStore::new(MemoryStore::new(), MAX_SIZE)
+    }
+
+    fn entries(store: &ChunkedStore<MemoryStore>) -> usize {
+        store.inner.list_accounts("BitwardenTest").unwrap().len()
+    }
+
+    /// Counts how often the entries are listed.
+    #[derive(Default)]
+    struct ListCountingStore {
+        inner: MemoryStore,
+        lists: AtomicUsize,
+    }
+
+    impl CredentialStore for ListCountingStore {
+        fn get_password(&self, service: &str, account: &str) -> Result<Zeroizing<String>> {
+            self.inner.get_password(service, account)
+        }
+
+        fn set_password(&self, service: &str, account: &str, password: &str) -> Result<()> {
+            self.inner.set_password(service, account, password)
+        }
+
+        fn get_secret_bytes(&self, service: &str, account: &str) -> Result<Zeroizing<Vec<u8>>> {
+            self.inner.get_secret_bytes(service, account)
+        }
+
+        fn set_secret_bytes(&self, service: &str, account: &str, secret: &[u8]) -> Result<()> {
+            self.inner.set_secret_bytes(service, account, secret)
+        }
+
+        fn delete_password(&self, service: &str, account: &str) -> Result<()> {
+            self.inner.delete_password(service, account)
+        }
+
+        fn list_accounts(&self, service_prefix: &str) -> Result<Vec<Account>> {
+            self.lists.fetch_add(1, Ordering::Relaxed);
+            self.inner.list_accounts(service_prefix)
+        }
+    }
+
+    #[test]
+    fn test_small_secret() {
+        let store = store();
+        store
+            .set_password("BitwardenTest", "BitwardenTest", "Random")
+            .unwrap();
+        assert_eq!(
+            "Random",
+            *store
+                .get_password("BitwardenTest", "BitwardenTest")
+                .unwrap()
+        );
+        assert_eq!(entries(&store), 1);
+    }
+
+    #[test]
+    fn test_large_password() {
+        let store = store();
+        let password = "🔑 Random ".repeat(50);
+        store
+            .set_password("BitwardenTest", "BitwardenTest", &password)
+            .unwrap();
+
+        assert_eq!(
+            password,
+            *store
+                .get_password("BitwardenTest", "BitwardenTest")
+                .unwrap()
+        );
+        assert_eq!(entries(&store), 1 + password.len().div_ceil(MAX_SIZE));
+    }
+
+    #[test]
+    fn test_large_secret_bytes() {
+        let store = store();
+        let secret: Vec<u8> = (0..=255).cycle().take(MAX_SIZE * 3 + 1).collect();
+        store
+            .set_secret_bytes("BitwardenTest", "BitwardenTest", &secret)
+            .unwrap();
+
+        assert_eq!(
+            *store
+                .get_secret_bytes("BitwardenTest", "BitwardenTest")
+                .unwrap(),
+            secret
+        );
+        assert_eq!(entries(&store), 5);
+    }
+
+    #[test]
+    fn test_list_and_delete() {
+        let store = store();
+        store
+            .set_password("BitwardenTest", "Large", &"a".repeat(MAX_SIZE * 2))
+            .unwrap();
+        store
+            .set_password("BitwardenTest", "Small", "Random")
+            .unwrap();
+
+        let accounts = store.list_accounts("BitwardenTest").unwrap();
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0].account, "Large");
+        assert_eq!(accounts[1].account, "Small");
+
+        assert_eq!(store.delete_all("BitwardenTest").unwrap(), 2);
+        assert_eq!(entries(&store), 0);
+    }
+
+    #[test]
+    fn test_overwrite_removes_chunks() {
+        let store = store();
+        store
+            .set_password("BitwardenTest", "BitwardenTest", &"a".repeat(MAX_SIZE * 4))
+            .unwrap();
+        store
+            .set_password("BitwardenTest", "BitwardenTest", &"b".repeat(MAX_SIZE * 2))
+            .unwrap();
+        assert_eq!(entries(&store), 1 + 2);
+
+        store
+            .set_password("BitwardenTest", "BitwardenTest", "Random")
+            .unwrap();
+        assert_eq!(entries(&store), 1);
+    }
+
+    #[test]
+    fn test_small_write_skips_listing() {
+        let store = ChunkedStore::new(ListCountingStore::default(), MAX_SIZE);
+        store
+            .set_password("BitwardenTest", "BitwardenTest", "Random")
+            .unwrap();
+        store
+            .set_secret_bytes("BitwardenTest", "Bytes", b"Random")
+            .unwrap();
+        store
+            .set_password("BitwardenTest", "BitwardenTest", "Random2")
+            .unwrap();
+        assert_eq!(store.inner.lists.load(Ordering::Relaxed), 0);
+
+        // Chunked writes, and writes that replace a manifest, still remove the stale chunks
+        store
+            .set_password("BitwardenTest", "BitwardenTest", &"a".repeat(MAX_SIZE * 2))
+            .unwrap();
+        store
+            .set_password("BitwardenTest", "BitwardenTest", "Random")
+            .unwrap();
+        assert_eq!(store.inner.lists.load(Ordering::Relaxed), 2);
+        assert_eq!(
+            store
+                .inner
+                .inner
+                .list_accounts("BitwardenTest")
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_interrupted_write() {
+        let store = store();
+        let password = "a".repeat(MAX_SIZE * 2);
+        store
+            .set_password("BitwardenTest", "BitwardenTest", &password)
+            .unwrap();
+
+        // Chunks written by a write that failed before it replaced the manifest
+        store
+            .write_chunks("BitwardenTest", "BitwardenTest", &[b'b'; MAX_SIZE * 3])
+            .unwrap();
+        assert_eq!(
+            password,
+            *store
+                .get_password("BitwardenTest", "BitwardenTest")
+                .unwrap()
+        );
+
+        // The next write removes them
+        store
+            .set_password("BitwardenTest", "BitwardenTest", "Random")
+            .unwrap();
+        assert_eq!(entries(&store), 1);
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let store = store();
+        let password = "a".repeat(MAX_SIZE * 2);
+        assert!(store
+            .set_password_if_changed("BitwardenTest", "BitwardenTest", &password)
+            .unwrap());
+        assert!(!store
+            .set_password_if_changed("BitwardenTest", "BitwardenTest", &password)
+            .unwrap());
+
+        // Compared against the reassembled password rather than the manifest
+        assert!(!store
+            .compare_and_swap("BitwardenTest", "BitwardenTest", Some("a"), "Random")
+            .unwrap());
+        assert!(store
+            .compare_and_swap("BitwardenTest", "BitwardenTest", Some(&password), "Random")
+            .unwrap());
+        assert_eq!(
+            "Random",
+            *store
+                .get_password("BitwardenTest", "BitwardenTest")
+                .unwrap()
+        );
+        assert_eq!(entries(&store), 1);
+    }
+
+    #[test]
+    fn test_entry_metadata() {
+        let store = store();
+        let password = "a".repeat(MAX_SIZE * 2);
+        let metadata = EntryMetadata {
+            label: Some("Biometric unlock".to_owned()),
+            comment: None,
+        };
+        store
+            .set_password_with_metadata("BitwardenTest", "BitwardenTest", &password, &metadata)
+            .unwrap();
+
+        let entry = store.get_entry("BitwardenTest", "BitwardenTest").unwrap();
+        assert_eq!(*entry.password, password);
+        assert_eq!(entry.label, metadata.label);
+        assert!(entry.created.is_some());
+    }
+
+    #[test]
+    fn test_secret_like_manifest() {
+        let store = store();
+        let password = manifest(1, 2, 10);
+        store
+            .set_password("BitwardenTest", "BitwardenTest", &password)
+            .unwrap();
+        assert_eq!(
+            password,
+            *store
+                .get_password("BitwardenTest", "BitwardenTest")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_error_missing_chunk() {
+        let store = store();
+        store
+            .set_password("BitwardenTest", "BitwardenTest", &"a".repeat(MAX_SIZE * 2))
+            .unwrap();
+        let manifest = store
+            .inner
+            .get_password("BitwardenTest", "BitwardenTest")
+            .unwrap();
+        let (generation, _, _) =
+            parse_manifest(manifest.strip_prefix(MANIFEST_PREFIX).unwrap().as_bytes()).unwrap();
+        store
+            .inner
+            .delete_password(
+                "BitwardenTest",
+                &chunk_account("BitwardenTest", generation, 1),
+            )
+            .unwrap();
+
+        assert!(matches!(
+            store.get_password("BitwardenTest", "BitwardenTest"),
+            Err(KeychainError::InvalidData(_))
+        ));
+
+        // Deleting still removes what is left
+        store
+            .delete_password("BitwardenTest", "BitwardenTest")
+            .unwrap();
+        assert_eq!(entries(&store), 0);
+    }
+}
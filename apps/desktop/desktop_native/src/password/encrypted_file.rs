@@ -0,0 +1,522 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD as base64_engine, Engine};
+use zeroize::Zeroizing;
+
+use super::{
+    insert_entry, swap_entry, Account, CredentialStore, Entries, Entry, EntryMetadata, Result,
+    StoredSecret,
+};
+use crate::{
+    crypto::{self, CipherString, SymmetricCryptoKey},
+    error::KeychainError,
+};
+
+/// Stores the passwords in a single file, encrypted as one `AesCbc256_HmacSha256_B64`
+/// CipherString so neither the secrets nor the service and account names are readable on disk.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    key: SymmetricCryptoKey,
+    // Serializes the read-modify-write cycles within this process
+    lock: Mutex<()>,
+}
+
+impl EncryptedFileStore {
+    /// The key must contain a MAC key, since the file is authenticated.
+    pub fn new(path: impl Into<PathBuf>, key: SymmetricCryptoKey) -> Result<Self> {
+        if key.mac_key().is_none() {
+            return Err(KeychainError::InvalidData(
+                "the file key must be 64 bytes".to_owned(),
+            ));
+        }
+
+        Ok(EncryptedFileStore {
+            path: path.into(),
+            key,
+            lock: Mutex::new(()),
+        })
+    }
+
+    fn read(&self) -> Result<Entries> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Entries::new()),
+            Err(e) => return Err(convert_io_error(e)),
+        };
+
+        let cipher: CipherString = contents.trim().parse().map_err(invalid_data)?;
+        let decrypted = crypto::decrypt(&cipher, &self.key).map_err(invalid_data)?;
+        let decrypted = std::str::from_utf8(&decrypted).map_err(invalid_data)?;
+
+        let mut entries = Entries::new();
+        for line in decrypted.lines() {
+            // Files written before the metadata was kept only contain the first three parts
+            let parts: Vec<&str> = line.split(':').collect();
+            let (service, account, secret, metadata) = match parts.as_slice() {
+                [service, account, secret] => (service, account, secret, None),
+                [service, account, secret, created, modified, label, comment] => (
+                    service,
+                    account,
+                    secret,
+                    Some((created, modified, label, comment)),
+                ),
+                _ => return Err(invalid_data("malformed entry")),
+            };
+
+            let secret = Zeroizing::new(base64_engine.decode(secret).map_err(invalid_data)?);
+            let mut entry = StoredSecret {
+                secret,
+                created: None,
+                modified: None,
+                metadata: EntryMetadata::default(),
+            };
+            if let Some((created, modified, label, comment)) = metadata {
+                entry.created = decode_time(created)?;
+                entry.modified = decode_time(modified)?;
+                entry.metadata.label = decode_optional(label)?;
+                entry.metadata.comment = decode_optional(comment)?;
+            }
+
+            entries.insert((decode_string(service)?, decode_string(account)?), entry);
+        }
+
+        Ok(entries)
+    }
+
+    fn write(&self, entries: &Entries) -> Result<()> {
+        let mut plaintext = Zeroizing::new(String::new());
+        for ((service, account), entry) in entries {
+            plaintext.push_str(&base64_engine.encode(service));
+            plaintext.push(':');
+            plaintext.push_str(&base64_engine.encode(account));
+            plaintext.push(':');
+            plaintext.push_str(&Zeroizing::new(
+                base64_engine.encode(entry.secret.as_slice()),
+            ));
+            plaintext.push(':');
+            plaintext.push_str(&encode_time(entry.created));
+            plaintext.push(':');
+            plaintext.push_str(&encode_time(entry.modified));
+            plaintext.push(':');
+            plaintext
+                .push_str(&base64_engine.encode(entry.metadata.label.as_deref().unwrap_or("")));
+            plaintext.push(':');
+            plaintext
+                .push_str(&base64_engine.encode(entry.metadata.comment.as_deref().unwrap_or("")));
+            plaintext.push('\n');
+        }
+
+        let cipher = crypto::encrypt(plaintext.as_bytes(), &self.key).map_err(invalid_data)?;
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(convert_io_error)?;
+        }
+
+        // Write to a temporary file first, so a crash never leaves a truncated file behind
+        let temp_path = self.path.with_extension("tmp");
+        write_private(&temp_path, cipher.to_string().as_bytes()).map_err(convert_io_error)?;
+        std::fs::rename(&temp_path, &self.path).map_err(convert_io_error)
+    }
+
+    fn update<T>(&self, f: impl FnOnce(&mut Entries) -> Result<T>) -> Result<T> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entries = self.read()?;
+        let result = f(&mut entries)?;
+        self.write(&entries)?;
+        Ok(result)
+    }
+
+    // Like `swap_entry`, but only rewrites the file when the entry changed
+    fn swap(
+        &self,
+        service: &str,
+        account: &str,
+        matches: impl FnOnce(Option<&[u8]>) -> bool,
+        secret: &[u8],
+    ) -> Result<bool> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entries = self.read()?;
+        if !swap_entry(&mut entries, service, account, matches, secret) {
+            return Ok(false);
+        }
+
+        self.write(&entries)?;
+        Ok(true)
+    }
+}
+
+impl CredentialStore for EncryptedFileStore {
+    fn get_entry(&self, service: &str, account: &str) -> Result<Entry> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.read()?
+            .get(&(service.to_owned(), account.to_owned()))
+            .ok_or(KeychainError::NotFound)?
+            .to_entry()
+    }
+
+    fn set_password_with_metadata(
+        &self,
+        service: &str,
+        account: &str,
+        password: &str,
+        metadata: &EntryMetadata,
+    ) -> Result<()> {
+        self.update(|entries| {
+            insert_entry(entries, service, account, password.as_bytes(), metadata);
+            Ok(())
+        })
+    }
+
+    fn set_password_if_changed(
+        &self,
+        service: &str,
+        account: &str,
+        password: &str,
+    ) -> Result<bool> {
+        self.swap(
+            service,
+            account,
+            |current| current != Some(password.as_bytes()),
+            password.as_bytes(),
+        )
+    }
+
+    fn compare_and_swap(
+        &self,
+        service: &str,
+        account: &str,
+        expected: Option<&str>,
+        password: &str,
+    ) -> Result<bool> {
+        self.swap(
+            service,
+            account,
+            |current| current == expected.map(str::as_bytes),
+            password.as_bytes(),
+        )
+    }
+
+    fn get_secret_bytes(&self, service: &str, account: &str) -> Result<Zeroizing<Vec<u8>>> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.read()?
+            .remove(&(service.to_owned(), account.to_owned()))
+            .map(|entry| entry.secret)
+            .ok_or(KeychainError::NotFound)
+    }
+
+    fn set_secret_bytes(&self, service: &str, account: &str, secret: &[u8]) -> Result<()> {
+        self.update(|entries| {
+            insert_entry(entries, service, account, secret, &EntryMetadata::default());
+            Ok(())
+        })
+    }
+
+    fn delete_password(&self, service: &str, account: &str) -> Result<()> {
+        self.update(|entries| {
+            entries
+                .remove(&(service.to_owned(), account.to_owned()))
+                .map(|_| ())
+                .ok_or(KeychainError::NotFound)
+        })
+    }
+
+    fn list_accounts(&self, service_prefix: &str) -> Result<Vec<Account>> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(self
+            .read()?
+            .into_keys()
+            .filter(|(service, _)| service.starts_with(service_prefix))
+            .map(|(service, account)| Account { service, account })
+            .collect())
+    }
+
+    // Rewrite the file once rather than once per entry
+    fn delete_all(&self, service_prefix: &str) -> Result<usize> {
+        self.update(|entries| {
+            let before = entries.len();
+            entries.retain(|(service, _), _| !service.starts_with(service_prefix));
+            Ok(before - entries.len())
+        })
+    }
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::{io::Write, os::unix::fs::OpenOptionsExt};
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    std::fs::write(path, contents)
+}
+
+fn decode_string(part: &str) -> Result<String> {
+    String::from_utf8(base64_engine.decode(part).map_err(invalid_data)?).map_err(invalid_data)
+}
+
+// Empty parts mean the value is not set
+fn decode_optional(part: &str) -> Result<Option<String>> {
+    match part.is_empty() {
+        true => Ok(None),
+        false => decode_string(part).map(Some),
+    }
+}
+
+// Times are stored as seconds since the Unix epoch
+fn encode_time(time: Option<SystemTime>) -> String {
+    time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|time| time.as_secs().to_string())
+        .unwrap_or_default()
+}
+
+fn decode_time(part: &str) -> Result<Option<SystemTime>> {
+    match part.is_empty() {
+        true => Ok(None),
+        false => {
+            let seconds = part.parse().map_err(invalid_data)?;
+            Ok(Some(UNIX_EPOCH + Duration::from_secs(seconds)))
+        }
+    }
+}
+
+fn convert_io_error(error: std::io::Error) -> KeychainError {
+    match error.kind() {
+        ErrorKind::PermissionDenied => KeychainError::AccessDenied,
+        _ => KeychainError::BackendUnavailable(error.to_string()),
+    }
+}
+
+fn invalid_data(error: impl std::fmt::Display) -> KeychainError {
+    KeychainError::InvalidData(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> SymmetricCryptoKey {
+        let key: Vec<u8> = (0..64).collect();
+        SymmetricCryptoKey::try_from(key.as_slice()).unwrap()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("bitwarden-store-{}", std::process::id()))
+            .join(name)
+    }
+
+    #[test]
+    fn test() {
+        let path = temp_path("test");
+        scopeguard::defer!(let _ = std::fs::remove_file(&path););
+
+        let store = EncryptedFileStore::new(&path, key()).unwrap();
+        store
+            .set_password("BitwardenTest", "BitwardenTest", "Random")
+            .unwrap();
+        assert_eq!(
+            "Random",
+            *store
+                .get_password("BitwardenTest", "BitwardenTest")
+                .unwrap()
+        );
+
+        // The entries are persisted and not readable on disk
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with("2."));
+        assert!(!contents.contains("Random"));
+        let reopened = EncryptedFileStore::new(&path, key()).unwrap();
+        assert_eq!(
+            reopened.list_accounts("Bitwarden").unwrap(),
+            [Account {
+                service: "BitwardenTest".to_owned(),
+                account: "BitwardenTest".to_owned(),
+            }]
+        );
+
+        store
+            .delete_password("BitwardenTest", "BitwardenTest")
+            .unwrap();
+
+        // Ensure password is deleted
+        match reopened.get_password("BitwardenTest", "BitwardenTest") {
+            Ok(_) => panic!("Got a result"),
+            Err(e) => assert!(matches!(e, KeychainError::NotFound)),
+        }
+    }
+
+    #[test]
+    fn test_delete_all() {
+        let path = temp_path("delete_all");
+        scopeguard::defer!(let _ = std::fs::remove_file(&path););
+
+        let store = EncryptedFileStore::new(&path, key()).unwrap();
+        store.set_password("BitwardenTest", "a", "Random").unwrap();
+        store
+            .set_password("BitwardenTest_biometric", "a", "Random")
+            .unwrap();
+        store.set_password("Other", "a", "Random").unwrap();
+
+        assert_eq!(store.delete_all("BitwardenTest").unwrap(), 2);
+        assert!(store.list_accounts("BitwardenTest").unwrap().is_empty());
+        assert_eq!(*store.get_password("Other", "a").unwrap(), "Random");
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let path = temp_path("compare_and_swap");
+        scopeguard::defer!(let _ = std::fs::remove_file(&path););
+
+        let store = EncryptedFileStore::new(&path, key()).unwrap();
+        assert!(store
+            .compare_and_swap("BitwardenTest", "BitwardenTest", None, "Random")
+            .unwrap());
+        assert!(!store
+            .compare_and_swap("BitwardenTest", "BitwardenTest", Some("Other"), "New")
+            .unwrap());
+
+        // Unchanged passwords don't rewrite the file
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert!(!store
+            .set_password_if_changed("BitwardenTest", "BitwardenTest", "Random")
+            .unwrap());
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().modified().unwrap(),
+            modified
+        );
+
+        assert!(store
+            .compare_and_swap("BitwardenTest", "BitwardenTest", Some("Random"), "New")
+            .unwrap());
+        assert_eq!(
+            "New",
+            *store
+                .get_password("BitwardenTest", "BitwardenTest")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_entry_metadata() {
+        let path = temp_path("metadata");
+        scopeguard::defer!(let _ = std::fs::remove_file(&path););
+
+        let store = EncryptedFileStore::new(&path, key()).unwrap();
+        let metadata = EntryMetadata {
+            label: Some("Biometric: unlock".to_owned()),
+            comment: None,
+        };
+        store
+            .set_password_with_metadata("BitwardenTest", "BitwardenTest", "Random", &metadata)
+            .unwrap();
+
+        let reopened = EncryptedFileStore::new(&path, key()).unwrap();
+        let entry = reopened
+            .get_entry("BitwardenTest", "BitwardenTest")
+            .unwrap();
+        assert_eq!(*entry.password, "Random");
+        assert_eq!(entry.label, metadata.label);
+        assert_eq!(entry.comment, None);
+        assert!(entry.created.is_some());
+        assert_eq!(entry.created, entry.modified);
+    }
+
+    #[test]
+    fn test_read_without_metadata() {
+        let path = temp_path("without_metadata");
+        scopeguard::defer!(let _ = std::fs::remove_file(&path););
+
+        // The format written before the metadata was kept
+        let line = format!(
+            "{}:{}:{}\n",
+            base64_engine.encode("BitwardenTest"),
+            base64_engine.encode("BitwardenTest"),
+            base64_engine.encode("Random")
+        );
+        let cipher = crypto::encrypt(line.as_bytes(), &key()).unwrap();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, cipher.to_string()).unwrap();
+
+        let store = EncryptedFileStore::new(&path, key()).unwrap();
+        let entry = store.get_entry("BitwardenTest", "BitwardenTest").unwrap();
+        assert_eq!(*entry.password, "Random");
+        assert_eq!(entry.created, None);
+        assert_eq!(entry.label, None);
+    }
+
+    #[test]
+    fn test_special_characters() {
+        let path = temp_path("special");
+        scopeguard::defer!(let _ = std::fs::remove_file(&path););
+
+        let store = EncryptedFileStore::new(&path, key()).unwrap();
+        store
+            .set_password("Bitwarden:Test", "line\nbreak", "pass:word\n🔑")
+            .unwrap();
+        assert_eq!(
+            "pass:word\n🔑",
+            *store.get_password("Bitwarden:Test", "line\nbreak").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_secret_bytes() {
+        let path = temp_path("bytes");
+        scopeguard::defer!(let _ = std::fs::remove_file(&path););
+
+        let secret: Vec<u8> = (0..=255).collect();
+        EncryptedFileStore::new(&path, key())
+            .unwrap()
+            .set_secret_bytes("BitwardenTest", "BitwardenTest", &secret)
+            .unwrap();
+
+        let store = EncryptedFileStore::new(&path, key()).unwrap();
+        assert_eq!(
+            *store
+                .get_secret_bytes("BitwardenTest", "BitwardenTest")
+                .unwrap(),
+            secret
+        );
+    }
+
+    #[test]
+    fn test_error_wrong_key() {
+        let path = temp_path("wrong_key");
+        scopeguard::defer!(let _ = std::fs::remove_file(&path););
+
+        let store = EncryptedFileStore::new(&path, key()).unwrap();
+        store
+            .set_password("BitwardenTest", "BitwardenTest", "Random")
+            .unwrap();
+
+        let wrong_key = SymmetricCryptoKey::try_from([1u8; 64].as_slice()).unwrap();
+        let store = EncryptedFileStore::new(&path, wrong_key).unwrap();
+        match store.get_password("BitwardenTest", "BitwardenTest") {
+            Ok(_) => panic!("Got a result"),
+            Err(e) => assert!(matches!(e, KeychainError::InvalidData(_))),
+        }
+    }
+
+    #[test]
+    fn test_error_no_password() {
+        let store = EncryptedFileStore::new(temp_path("missing"), key()).unwrap();
+        match store.get_password("BitwardenTest", "BitwardenTest") {
+            Ok(_) => panic!("Got a result"),
+            Err(e) => assert!(matches!(e, KeychainError::NotFound)),
+        }
+    }
+}
@@ -0,0 +1,495 @@
+//! Encrypted file keychain for Linux systems without a Secret Service provider, e.g. minimal
+//! window managers, servers and some Flatpak and Snap setups.
+
+use std::{
+    ffi::OsString,
+    fs::{DirBuilder, OpenOptions},
+    io::{ErrorKind, Read, Write},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use base64::{engine::general_purpose::STANDARD as base64_engine, Engine};
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+use super::{Account, CredentialStore, EncryptedFileStore, Entry, EntryMetadata, OsStore, Result};
+use crate::{crypto::SymmetricCryptoKey, error::KeychainError};
+
+const DIRECTORY_NAME: &str = "Bitwarden";
+const KEY_FILE_NAME: &str = "keychain.key";
+const STORE_FILE_NAME: &str = "keychain.dat";
+
+// Set once libsecret reports that no Secret Service provider is running, from then on the fallback
+// is used for the rest of the process so entries don't end up split between the two stores
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static FALLBACK: Mutex<Option<Arc<EncryptedFileStore>>> = Mutex::new(None);
+
+/// The Secret Service, switching to the encrypted file fallback when no provider is running.
+pub struct SecretServiceStore<S = OsStore> {
+    os: S,
+    force_fallback: bool,
+    // `None` means the data directory of the user
+    directory: Option<PathBuf>,
+}
+
+impl SecretServiceStore {
+    /// Use the Secret Service while it is available.
+    pub fn new() -> Self {
+        SecretServiceStore {
+            os: OsStore,
+            force_fallback: false,
+            directory: None,
+        }
+    }
+
+    /// Always use the encrypted file fallback.
+    pub fn fallback() -> Self {
+        SecretServiceStore {
+            os: OsStore,
+            force_fallback: true,
+            directory: None,
+        }
+    }
+}
+
+impl<S: CredentialStore> SecretServiceStore<S> {
+    fn run<T>(
+        &self,
+        os: impl FnOnce(&S) -> Result<T>,
+        fallback: impl FnOnce(&EncryptedFileStore) -> Result<T>,
+    ) -> Result<T> {
+        if !self.uses_fallback() {
+            match os(&self.os) {
+                Err(KeychainError::BackendUnavailable(_)) => ACTIVE.store(true, Ordering::SeqCst),
+                result => return result,
+            }
+        }
+
+        fallback(self.fallback_store()?.as_ref())
+    }
+
+    // Entries written while an earlier process used the fallback stay readable once the Secret
+    // Service is running again
+    fn read<T>(
+        &self,
+        os: impl FnOnce(&S) -> Result<T>,
+        fallback: impl Fn(&EncryptedFileStore) -> Result<T>,
+    ) -> Result<T> {
+        match self.run(os, &fallback) {
+            Err(KeychainError::NotFound) => match self.leftover_fallback_store()? {
+                Some(store) => fallback(store.as_ref()),
+                None => Err(KeychainError::NotFound),
+            },
+            result => result,
+        }
+    }
+
+    fn fallback_store(&self) -> Result<Arc<EncryptedFileStore>> {
+        if let Some(directory) = &self.directory {
+            return Ok(Arc::new(open(directory)?));
+        }
+
+        let mut fallback = FALLBACK.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(store) = fallback.as_ref() {
+            return Ok(store.clone());
+        }
+
+        let directory =
+            data_directory(std::env::var_os("XDG_DATA_HOME"), std::env::var_os("HOME"))?;
+        let store = Arc::new(open(&directory)?);
+        *fallback = Some(store.clone());
+        Ok(store)
+    }
+
+    /// The fallback store while the Secret Service is used, if an earlier process created it.
+    /// Its entries have to be listed and deleted along with those of the Secret Service, or they
+    /// would still be read after being deleted.
+    fn leftover_fallback_store(&self) -> Result<Option<Arc<EncryptedFileStore>>> {
+        if self.uses_fallback() {
+            return Ok(None);
+        }
+
+        let directory = match &self.directory {
+            Some(directory) => directory.clone(),
+            None => {
+                match data_directory(std::env::var_os("XDG_DATA_HOME"), std::env::var_os("HOME")) {
+                    Ok(directory) => directory,
+                    Err(_) => return Ok(None),
+                }
+            }
+        };
+        match directory.join(KEY_FILE_NAME).exists() {
+            true => self.fallback_store().map(Some),
+            false => Ok(None),
+        }
+    }
+}
+
+impl<S: CredentialStore> CredentialStore for SecretServiceStore<S> {
+    fn get_password(&self, service: &str, account: &str) -> Result<Zeroizing<String>> {
+        self.read(
+            |store| store.get_password(service, account),
+            |store| store.get_password(service, account),
+        )
+    }
+
+    fn set_password(&self, service: &str, account: &str, password: &str) -> Result<()> {
+        self.run(
+            |store| store.set_password(service, account, password),
+            |store| store.set_password(service, account, password),
+        )
+    }
+
+    fn set_password_if_changed(
+        &self,
+        service: &str,
+        account: &str,
+        password: &str,
+    ) -> Result<bool> {
+        self.run(
+            |store| store.set_password_if_changed(service, account, password),
+            |store| store.set_password_if_changed(service, account, password),
+        )
+    }
+
+    fn compare_and_swap(
+        &self,
+        service: &str,
+        account: &str,
+        expected: Option<&str>,
+        password: &str,
+    ) -> Result<bool> {
+        self.run(
+            |store| store.compare_and_swap(service, account, expected, password),
+            |store| store.compare_and_swap(service, account, expected, password),
+        )
+    }
+
+    fn get_entry(&self, service: &str, account: &str) -> Result<Entry> {
+        self.read(
+            |store| store.get_entry(service, account),
+            |store| store.get_entry(service, account),
+        )
+    }
+
+    fn set_password_with_metadata(
+        &self,
+        service: &str,
+        account: &str,
+        password: &str,
+        metadata: &EntryMetadata,
+    ) -> Result<()> {
+        self.run(
+            |store| store.set_password_with_metadata(service, account, password, metadata),
+            |store| store.set_password_with_metadata(service, account, password, metadata),
+        )
+    }
+
+    fn get_secret_bytes(&self, service: &str, account: &str) -> Result<Zeroizing<Vec<u8>>> {
+        self.read(
+            |store| store.get_secret_bytes(service, account),
+            |store| store.get_secret_bytes(service, account),
+        )
+    }
+
+    fn set_secret_bytes(&self, service: &str, account: &str, secret: &[u8]) -> Result<()> {
+        self.run(
+            |store| store.set_secret_bytes(service, account, secret),
+            |store| store.set_secret_bytes(service, account, secret),
+        )
+    }
+
+    fn delete_password(&self, service: &str, account: &str) -> Result<()> {
+        let deleted = self.run(
+            |store| store.delete_password(service, account),
+            |store| store.delete_password(service, account),
+        );
+        let Some(store) = self.leftover_fallback_store()? else {
+            return deleted;
+        };
+
+        match (deleted, store.delete_password(service, account)) {
+            (Err(KeychainError::NotFound), leftover) => leftover,
+            (deleted, Ok(()) | Err(KeychainError::NotFound)) => deleted,
+            (_, Err(e)) => Err(e),
+        }
+    }
+
+    // `delete_all` deletes what this lists, so it covers the leftover fallback entries as well
+    fn list_accounts(&self, service_prefix: &str) -> Result<Vec<Account>> {
+        let mut accounts = self.run(
+            |store| store.list_accounts(service_prefix),
+            |store| store.list_accounts(service_prefix),
+        )?;
+        if let Some(store) = self.leftover_fallback_store()? {
+            accounts.extend(store.list_accounts(service_prefix)?);
+            accounts.sort();
+            accounts.dedup();
+        }
+        Ok(accounts)
+    }
+
+    fn uses_fallback(&self) -> bool {
+        self.force_fallback || ACTIVE.load(Ordering::SeqCst)
+    }
+}
+
+/// Open the fallback store in the directory, generating the key on first use.
+fn open(directory: &Path) -> Result<EncryptedFileStore> {
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(directory)
+        .map_err(convert_io_error)?;
+
+    let key = load_or_create_key(&directory.join(KEY_FILE_NAME))?;
+    EncryptedFileStore::new(directory.join(STORE_FILE_NAME), key)
+}
+
+fn load_or_create_key(path: &Path) -> Result<SymmetricCryptoKey> {
+    match create_key(path) {
+        Ok(key) => return SymmetricCryptoKey::try_from(key.as_slice()).map_err(invalid_data),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(convert_io_error(e)),
+    }
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .open(path)
+        .map_err(convert_io_error)?;
+
+    // Like ssh, refuse to use a key that other users can read
+    let mode = file
+        .metadata()
+        .map_err(convert_io_error)?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(KeychainError::AccessDenied);
+    }
+
+    let mut encoded = Zeroizing::new(String::new());
+    file.read_to_string(&mut encoded)
+        .map_err(convert_io_error)?;
+    let key = Zeroizing::new(base64_engine.decode(encoded.trim()).map_err(invalid_data)?);
+    SymmetricCryptoKey::try_from(key.as_slice()).map_err(invalid_data)
+}
+
+/// Generate a key and store it at `path`, failing with `AlreadyExists` if another process got
+/// there first.
+///
+/// The key is written to a temporary file that is then linked into place, so the key file never
+/// exists without its full contents, and only one of the processes racing to create it wins.
+fn create_key(path: &Path) -> std::io::Result<Zeroizing<[u8; 64]>> {
+    let mut key = Zeroizing::new([0u8; 64]);
+    rand::thread_rng().fill_bytes(key.as_mut());
+
+    let mut suffix = [0u8; 8];
+    rand::thread_rng().fill_bytes(&mut suffix);
+    let mut temp_name = path.as_os_str().to_owned();
+    temp_name.push(format!(".{}.tmp", u64::from_le_bytes(suffix)));
+    let temp_path = PathBuf::from(temp_name);
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&temp_path)?;
+    scopeguard::defer!({
+        let _ = std::fs::remove_file(&temp_path);
+    });
+
+    let encoded = Zeroizing::new(base64_engine.encode(key.as_ref()));
+    file.write_all(encoded.as_bytes())?;
+    file.sync_all()?;
+    std::fs::hard_link(&temp_path, path)?;
+
+    Ok(key)
+}
+
+/// `$XDG_DATA_HOME/Bitwarden`, defaulting to `~/.local/share/Bitwarden`. Relative paths are
+/// ignored, as required by the XDG Base Directory specification.
+fn data_directory(xdg_data_home: Option<OsString>, home: Option<OsString>) -> Result<PathBuf> {
+    let xdg_data_home = xdg_data_home
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute());
+
+    let data_home = match xdg_data_home {
+        Some(path) => path,
+        None => home
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .map(|home| home.join(".local").join("share"))
+            .ok_or_else(|| {
+                KeychainError::BackendUnavailable(
+                    "neither XDG_DATA_HOME nor HOME is set".to_owned(),
+                )
+            })?,
+    };
+
+    Ok(data_home.join(DIRECTORY_NAME))
+}
+
+fn convert_io_error(error: std::io::Error) -> KeychainError {
+    match error.kind() {
+        ErrorKind::PermissionDenied => KeychainError::AccessDenied,
+        _ => KeychainError::BackendUnavailable(error.to_string()),
+    }
+}
+
+fn invalid_data(error: impl std::fmt::Display) -> KeychainError {
+    KeychainError::InvalidData(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::Permissions;
+
+    use super::*;
+    use crate::password::MemoryStore;
+
+    fn temp_directory(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("bitwarden-fallback-{}", std::process::id()))
+            .join(name)
+    }
+
+    #[test]
+    fn test_open() {
+        let directory = temp_directory("open");
+        scopeguard::defer!(let _ = std::fs::remove_dir_all(&directory););
+
+        let store = open(&directory).unwrap();
+        store
+            .set_password("BitwardenTest", "BitwardenTest", "Random")
+            .unwrap();
+
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&directory), 0o700);
+        assert_eq!(mode(&directory.join(KEY_FILE_NAME)), 0o600);
+        assert_eq!(mode(&directory.join(STORE_FILE_NAME)), 0o600);
+
+        // The generated key is reused
+        let reopened = open(&directory).unwrap();
+        assert_eq!(
+            "Random",
+            *reopened
+                .get_password("BitwardenTest", "BitwardenTest")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_error_key_readable_by_others() {
+        let directory = temp_directory("permissions");
+        scopeguard::defer!(let _ = std::fs::remove_dir_all(&directory););
+
+        open(&directory).unwrap();
+        std::fs::set_permissions(directory.join(KEY_FILE_NAME), Permissions::from_mode(0o644))
+            .unwrap();
+
+        assert!(matches!(open(&directory), Err(KeychainError::AccessDenied)));
+    }
+
+    #[test]
+    fn test_timeout_keeps_secret_service() {
+        let store = SecretServiceStore::new();
+
+        let timed_out: Result<()> = store.run(|_| Err(KeychainError::Timeout), |_| unreachable!());
+        assert!(matches!(timed_out, Err(KeychainError::Timeout)));
+
+        assert!(!store.uses_fallback());
+        assert_eq!(
+            store
+                .run(|_| Ok("Secret Service"), |_| Ok("fallback"))
+                .unwrap(),
+            "Secret Service"
+        );
+    }
+
+    #[test]
+    fn test_delete_leftover_fallback_entry() {
+        let directory = temp_directory("leftover");
+        scopeguard::defer!(let _ = std::fs::remove_dir_all(&directory););
+
+        // Written by an earlier process, while no Secret Service provider was running
+        open(&directory)
+            .unwrap()
+            .set_password("BitwardenTest", "BitwardenTest", "Random")
+            .unwrap();
+
+        let store = SecretServiceStore {
+            os: MemoryStore::new(),
+            force_fallback: false,
+            directory: Some(directory.clone()),
+        };
+        store
+            .set_password("BitwardenTest", "Other", "Random")
+            .unwrap();
+
+        assert_eq!(
+            *store
+                .get_password("BitwardenTest", "BitwardenTest")
+                .unwrap(),
+            "Random"
+        );
+        assert_eq!(
+            store.list_accounts("BitwardenTest").unwrap(),
+            [
+                Account {
+                    service: "BitwardenTest".to_owned(),
+                    account: "BitwardenTest".to_owned(),
+                },
+                Account {
+                    service: "BitwardenTest".to_owned(),
+                    account: "Other".to_owned(),
+                },
+            ]
+        );
+
+        store
+            .delete_password("BitwardenTest", "BitwardenTest")
+            .unwrap();
+        assert!(matches!(
+            store.get_password("BitwardenTest", "BitwardenTest"),
+            Err(KeychainError::NotFound)
+        ));
+        assert!(matches!(
+            store.delete_password("BitwardenTest", "BitwardenTest"),
+            Err(KeychainError::NotFound)
+        ));
+
+        open(&directory)
+            .unwrap()
+            .set_password("BitwardenTest", "BitwardenTest", "Random")
+            .unwrap();
+        assert_eq!(store.delete_all("BitwardenTest").unwrap(), 2);
+        assert!(store.list_accounts("BitwardenTest").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_data_directory() {
+        let directory = |xdg: Option<&str>, home: Option<&str>| {
+            data_directory(xdg.map(OsString::from), home.map(OsString::from))
+        };
+
+        assert_eq!(
+            directory(Some("/data"), Some("/home/user")).unwrap(),
+            Path::new("/data/Bitwarden")
+        );
+        assert_eq!(
+            directory(None, Some("/home/user")).unwrap(),
+            Path::new("/home/user/.local/share/Bitwarden")
+        );
+        assert_eq!(
+            directory(Some("relative"), Some("/home/user")).unwrap(),
+            Path::new("/home/user/.local/share/Bitwarden")
+        );
+        assert!(directory(None, None).is_err());
+    }
+}
@@ -1,27 +1,250 @@
-use anyhow::Result;
-use security_framework::passwords::{
-    delete_generic_password, get_generic_password, set_generic_password,
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use core_foundation::{
+    base::{CFType, CFTypeRef, OSStatus, TCFType},
+    boolean::CFBoolean,
+    data::CFData,
+    date::CFDate,
+    dictionary::{CFDictionary, CFDictionaryRef},
+    string::{CFString, CFStringRef},
+    ConcreteCFType,
+};
+use security_framework::{
+    base,
+    item::{ItemClass, ItemSearchOptions, Limit},
+    passwords::{delete_generic_password, get_generic_password, set_generic_password},
 };
+use security_framework_sys::{
+    base::{
+        errSecAuthFailed, errSecInteractionNotAllowed, errSecItemNotFound, errSecNotAvailable,
+        errSecSuccess, errSecUserCanceled,
+    },
+    item::{
+        kSecAttrAccount, kSecAttrLabel, kSecAttrService, kSecClass, kSecClassGenericPassword,
+        kSecReturnAttributes, kSecReturnData, kSecValueData,
+    },
+    keychain_item::{SecItemAdd, SecItemCopyMatching, SecItemUpdate},
+};
+use zeroize::Zeroizing;
 
-pub fn get_password(service: &str, account: &str) -> Result<String> {
-    let result = String::from_utf8(get_generic_password(&service, &account)?)?;
-    Ok(result)
+use super::{Account, Entry, EntryMetadata};
+use crate::error::KeychainError;
+
+type Result<T> = std::result::Result<T, KeychainError>;
+
+// Not exposed by security-framework-sys
+extern "C" {
+    static kSecAttrComment: CFStringRef;
+    static kSecAttrCreationDate: CFStringRef;
+    static kSecAttrModificationDate: CFStringRef;
 }
 
-pub fn get_password_keytar(service: &str, account: &str) -> Result<String> {
+// Seconds between the Unix epoch and the Core Foundation epoch, 2001-01-01
+const CF_ABSOLUTE_TIME_OFFSET: f64 = 978_307_200.0;
+
+pub fn get_password(service: &str, account: &str) -> Result<Zeroizing<String>> {
+    let result =
+        String::from_utf8(get_generic_password(&service, &account).map_err(convert_error)?)
+            .map_err(|e| KeychainError::InvalidData(e.to_string()))?;
+    Ok(Zeroizing::new(result))
+}
+
+pub fn get_password_keytar(service: &str, account: &str) -> Result<Zeroizing<String>> {
     get_password(service, account)
 }
 
+// Like the other backends, replaces the label and comment of an existing item with the defaults
 pub fn set_password(service: &str, account: &str, password: &str) -> Result<()> {
-    let result = set_generic_password(&service, &account, password.as_bytes())?;
-    Ok(result)
+    set_password_with_metadata(service, account, password, &EntryMetadata::default())
+}
+
+pub fn get_entry(service: &str, account: &str) -> Result<Entry> {
+    let query = item_query(
+        service,
+        account,
+        &[
+            (
+                unsafe { kSecReturnAttributes },
+                CFBoolean::true_value().as_CFType(),
+            ),
+            (
+                unsafe { kSecReturnData },
+                CFBoolean::true_value().as_CFType(),
+            ),
+        ],
+    );
+
+    let mut result: CFTypeRef = std::ptr::null();
+    cvt(unsafe { SecItemCopyMatching(query.as_concrete_TypeRef(), &mut result) })?;
+    let attributes: CFDictionary<CFString, CFType> =
+        unsafe { CFDictionary::wrap_under_create_rule(result as CFDictionaryRef) };
+
+    let data = find::<CFData>(&attributes, unsafe { kSecValueData })
+        .ok_or_else(|| KeychainError::InvalidData("the item has no data".to_owned()))?;
+    let password = String::from_utf8(data.bytes().to_vec())
+        .map_err(|e| KeychainError::InvalidData(e.to_string()))?;
+
+    Ok(Entry {
+        password: Zeroizing::new(password),
+        created: find::<CFDate>(&attributes, unsafe { kSecAttrCreationDate })
+            .and_then(date_to_system_time),
+        modified: find::<CFDate>(&attributes, unsafe { kSecAttrModificationDate })
+            .and_then(date_to_system_time),
+        label: find::<CFString>(&attributes, unsafe { kSecAttrLabel }).map(|s| s.to_string()),
+        // Items without a comment store an empty one
+        comment: find::<CFString>(&attributes, unsafe { kSecAttrComment })
+            .map(|s| s.to_string())
+            .filter(|comment| !comment.is_empty()),
+    })
+}
+
+pub fn set_password_with_metadata(
+    service: &str,
+    account: &str,
+    password: &str,
+    metadata: &EntryMetadata,
+) -> Result<()> {
+    let attributes = [
+        (
+            unsafe { kSecValueData },
+            CFData::from_buffer(password.as_bytes()).as_CFType(),
+        ),
+        // Keychain Access shows the service when there is no label
+        (
+            unsafe { kSecAttrLabel },
+            CFString::new(metadata.label.as_deref().unwrap_or(service)).as_CFType(),
+        ),
+        (
+            unsafe { kSecAttrComment },
+            CFString::new(metadata.comment.as_deref().unwrap_or("")).as_CFType(),
+        ),
+    ];
+
+    let update = item_query(service, account, &[]);
+    let status = unsafe {
+        SecItemUpdate(
+            update.as_concrete_TypeRef(),
+            to_dictionary(&attributes).as_concrete_TypeRef(),
+        )
+    };
+    if status != errSecItemNotFound {
+        return cvt(status);
+    }
+
+    let add = item_query(service, account, &attributes);
+    cvt(unsafe { SecItemAdd(add.as_concrete_TypeRef(), std::ptr::null_mut()) })
+}
+
+pub fn get_secret_bytes(service: &str, account: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let result = get_generic_password(&service, &account).map_err(convert_error)?;
+    Ok(Zeroizing::new(result))
+}
+
+pub fn set_secret_bytes(service: &str, account: &str, secret: &[u8]) -> Result<()> {
+    set_generic_password(&service, &account, secret).map_err(convert_error)
 }
 
 pub fn delete_password(service: &str, account: &str) -> Result<()> {
-    let result = delete_generic_password(&service, &account)?;
+    let result = delete_generic_password(&service, &account).map_err(convert_error)?;
     Ok(result)
 }
 
+pub fn list_accounts(service_prefix: &str) -> Result<Vec<Account>> {
+    // Searches with SecItemCopyMatching. Only the attributes are loaded, so reading them doesn't
+    // prompt the user
+    let results = match ItemSearchOptions::new()
+        .class(ItemClass::generic_password())
+        .load_attributes(true)
+        .limit(Limit::All)
+        .search()
+    {
+        Ok(results) => results,
+        Err(e) if e.code() == errSecItemNotFound => return Ok(Vec::new()),
+        Err(e) => return Err(convert_error(e)),
+    };
+
+    Ok(results
+        .iter()
+        .filter_map(|result| {
+            let mut attributes = result.simplify_dict()?;
+            Some(Account {
+                service: attributes.remove("svce")?,
+                account: attributes.remove("acct")?,
+            })
+        })
+        .filter(|entry| entry.service.starts_with(service_prefix))
+        .collect())
+}
+
+// A query for the generic password item, with additional attributes
+fn item_query(
+    service: &str,
+    account: &str,
+    attributes: &[(CFStringRef, CFType)],
+) -> CFDictionary<CFString, CFType> {
+    let mut pairs = vec![
+        (unsafe { kSecClass }, unsafe {
+            CFString::wrap_under_get_rule(kSecClassGenericPassword).as_CFType()
+        }),
+        (
+            unsafe { kSecAttrService },
+            CFString::new(service).as_CFType(),
+        ),
+        (
+            unsafe { kSecAttrAccount },
+            CFString::new(account).as_CFType(),
+        ),
+    ];
+    pairs.extend_from_slice(attributes);
+    to_dictionary(&pairs)
+}
+
+fn to_dictionary(pairs: &[(CFStringRef, CFType)]) -> CFDictionary<CFString, CFType> {
+    let pairs: Vec<_> = pairs
+        .iter()
+        .map(|(key, value)| {
+            (
+                unsafe { CFString::wrap_under_get_rule(*key) },
+                value.clone(),
+            )
+        })
+        .collect();
+    CFDictionary::from_CFType_pairs(&pairs)
+}
+
+fn find<T: ConcreteCFType>(
+    attributes: &CFDictionary<CFString, CFType>,
+    key: CFStringRef,
+) -> Option<T> {
+    attributes
+        .find(unsafe { CFString::wrap_under_get_rule(key) })?
+        .downcast::<T>()
+}
+
+fn date_to_system_time(date: CFDate) -> Option<SystemTime> {
+    let since_unix_epoch = Duration::try_from_secs_f64(date.abs_time() + CF_ABSOLUTE_TIME_OFFSET);
+    Some(UNIX_EPOCH + since_unix_epoch.ok()?)
+}
+
+fn cvt(status: OSStatus) -> Result<()> {
+    match status {
+        errSecSuccess => Ok(()),
+        _ => Err(convert_error(base::Error::from_code(status))),
+    }
+}
+
+// Convert the keychain status codes to a `KeychainError`
+fn convert_error(error: base::Error) -> KeychainError {
+    match error.code() {
+        errSecItemNotFound => KeychainError::NotFound,
+        errSecAuthFailed | errSecUserCanceled => KeychainError::AccessDenied,
+        // Returned when the keychain is locked and we aren't allowed to prompt for unlocking it
+        errSecInteractionNotAllowed => KeychainError::Locked,
+        errSecNotAvailable => KeychainError::BackendUnavailable(error.to_string()),
+        _ => KeychainError::Other(error.to_string()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -32,28 +255,57 @@ mod tests {
         set_password("BitwardenTest", "BitwardenTest", "Random").unwrap();
         assert_eq!(
             "Random",
-            get_password("BitwardenTest", "BitwardenTest").unwrap()
+            *get_password("BitwardenTest", "BitwardenTest").unwrap()
         );
         delete_password("BitwardenTest", "BitwardenTest").unwrap();
 
         // Ensure password is deleted
         match get_password("BitwardenTest", "BitwardenTest") {
             Ok(_) => panic!("Got a result"),
-            Err(e) => assert_eq!(
-                "The specified item could not be found in the keychain.",
-                e.to_string()
-            ),
+            Err(e) => assert!(matches!(e, KeychainError::NotFound)),
         }
     }
 
+    #[test]
+    fn test_secret_bytes() {
+        scopeguard::defer!(delete_password("BitwardenTest", "BitwardenTest").unwrap_or({}););
+        let secret = [0u8, 159, 146, 150, 255];
+        set_secret_bytes("BitwardenTest", "BitwardenTest", &secret).unwrap();
+        assert_eq!(
+            *get_secret_bytes("BitwardenTest", "BitwardenTest").unwrap(),
+            secret
+        );
+    }
+
+    #[test]
+    fn test_entry_metadata() {
+        scopeguard::defer!(delete_password("BitwardenTest", "BitwardenTest").unwrap_or({}););
+        let metadata = EntryMetadata {
+            label: Some("Biometric unlock".to_owned()),
+            comment: Some("Configured on this device".to_owned()),
+        };
+        set_password_with_metadata("BitwardenTest", "BitwardenTest", "Random", &metadata).unwrap();
+
+        let entry = get_entry("BitwardenTest", "BitwardenTest").unwrap();
+        assert_eq!(*entry.password, "Random");
+        assert_eq!(entry.label, metadata.label);
+        assert_eq!(entry.comment, metadata.comment);
+        assert!(entry.created.unwrap() <= entry.modified.unwrap());
+
+        // Updating keeps the item and its creation date, but replaces the metadata
+        set_password("BitwardenTest", "BitwardenTest", "Other").unwrap();
+        let updated = get_entry("BitwardenTest", "BitwardenTest").unwrap();
+        assert_eq!(*updated.password, "Other");
+        assert_eq!(updated.created, entry.created);
+        assert_eq!(updated.label.as_deref(), Some("BitwardenTest"));
+        assert_eq!(updated.comment, None);
+    }
+
     #[test]
     fn test_error_no_password() {
         match get_password("Unknown", "Unknown") {
             Ok(_) => panic!("Got a result"),
-            Err(e) => assert_eq!(
-                "The specified item could not be found in the keychain.",
-                e.to_string()
-            ),
+            Err(e) => assert!(matches!(e, KeychainError::NotFound)),
         }
     }
 }
@@ -0,0 +1,310 @@
+use std::sync::{Mutex, MutexGuard};
+
+use zeroize::Zeroizing;
+
+use super::{
+    insert_entry, swap_entry, Account, CredentialStore, Entries, Entry, EntryMetadata, Result,
+};
+use crate::error::KeychainError;
+
+/// Keeps the passwords in process memory, nothing is persisted. Useful for tests and machines
+/// without a keychain.
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: Mutex<Entries>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Entries> {
+        // A panic while holding the lock can't leave the map in an inconsistent state
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl CredentialStore for MemoryStore {
+    fn set_password_if_changed(
+        &self,
+        service: &str,
+        account: &str,
+        password: &str,
+    ) -> Result<bool> {
+        Ok(swap_entry(
+            &mut self.entries(),
+            service,
+            account,
+            |current| current != Some(password.as_bytes()),
+            password.as_bytes(),
+        ))
+    }
+
+    fn compare_and_swap(
+        &self,
+        service: &str,
+        account: &str,
+        expected: Option<&str>,
+        password: &str,
+    ) -> Result<bool> {
+        Ok(swap_entry(
+            &mut self.entries(),
+            service,
+            account,
+            |current| current == expected.map(str::as_bytes),
+            password.as_bytes(),
+        ))
+    }
+
+    fn get_entry(&self, service: &str, account: &str) -> Result<Entry> {
+        self.entries()
+            .get(&(service.to_owned(), account.to_owned()))
+            .ok_or(KeychainError::NotFound)?
+            .to_entry()
+    }
+
+    fn set_password_with_metadata(
+        &self,
+        service: &str,
+        account: &str,
+        password: &str,
+        metadata: &EntryMetadata,
+    ) -> Result<()> {
+        insert_entry(
+            &mut self.entries(),
+            service,
+            account,
+            password.as_bytes(),
+            metadata,
+        );
+        Ok(())
+    }
+
+    fn get_secret_bytes(&self, service: &str, account: &str) -> Result<Zeroizing<Vec<u8>>> {
+        self.entries()
+            .get(&(service.to_owned(), account.to_owned()))
+            .map(|entry| entry.secret.clone())
+            .ok_or(KeychainError::NotFound)
+    }
+
+    fn set_secret_bytes(&self, service: &str, account: &str, secret: &[u8]) -> Result<()> {
+        insert_entry(
+            &mut self.entries(),
+            service,
+            account,
+            secret,
+            &EntryMetadata::default(),
+        );
+        Ok(())
+    }
+
+    fn delete_password(&self, service: &str, account: &str) -> Result<()> {
+        self.entries()
+            .remove(&(service.to_owned(), account.to_owned()))
+            .map(|_| ())
+            .ok_or(KeychainError::NotFound)
+    }
+
+    fn list_accounts(&self, service_prefix: &str) -> Result<Vec<Account>> {
+        Ok(self
+            .entries()
+            .keys()
+            .filter(|(service, _)| service.starts_with(service_prefix))
+            .map(|(service, account)| Account {
+                service: service.clone(),
+                account: account.clone(),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let store = MemoryStore::new();
+        store
+            .set_password("BitwardenTest", "BitwardenTest", "Random")
+            .unwrap();
+        assert_eq!(
+            "Random",
+            *store
+                .get_password("BitwardenTest", "BitwardenTest")
+                .unwrap()
+        );
+        store
+            .delete_password("BitwardenTest", "BitwardenTest")
+            .unwrap();
+
+        // Ensure password is deleted
+        match store.get_password("BitwardenTest", "BitwardenTest") {
+            Ok(_) => panic!("Got a result"),
+            Err(e) => assert!(matches!(e, KeychainError::NotFound)),
+        }
+    }
+
+    fn account(service: &str, account: &str) -> Account {
+        Account {
+            service: service.to_owned(),
+            account: account.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_list_accounts() {
+        let store = MemoryStore::new();
+        store.set_password("BitwardenTest", "b", "Random").unwrap();
+        store.set_password("BitwardenTest", "a", "Random").unwrap();
+        store
+            .set_password("BitwardenTest_biometric", "a", "Random")
+            .unwrap();
+        store.set_password("Other", "c", "Random").unwrap();
+
+        assert_eq!(
+            store.list_accounts("BitwardenTest").unwrap(),
+            [
+                account("BitwardenTest", "a"),
+                account("BitwardenTest", "b"),
+                account("BitwardenTest_biometric", "a"),
+            ]
+        );
+        assert!(store.list_accounts("Unknown").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_secret_bytes() {
+        let store = MemoryStore::new();
+        let secret = [0u8, 159, 146, 150, 255];
+        store
+            .set_secret_bytes("BitwardenTest", "BitwardenTest", &secret)
+            .unwrap();
+        assert_eq!(
+            *store
+                .get_secret_bytes("BitwardenTest", "BitwardenTest")
+                .unwrap(),
+            secret
+        );
+
+        // The bytes are not valid UTF-8, so they can't be read as a password
+        match store.get_password("BitwardenTest", "BitwardenTest") {
+            Ok(_) => panic!("Got a result"),
+            Err(e) => assert!(matches!(e, KeychainError::InvalidData(_))),
+        }
+    }
+
+    #[test]
+    fn test_set_password_if_changed() {
+        let store = MemoryStore::new();
+        assert!(store
+            .set_password_if_changed("BitwardenTest", "BitwardenTest", "Random")
+            .unwrap());
+        assert!(!store
+            .set_password_if_changed("BitwardenTest", "BitwardenTest", "Random")
+            .unwrap());
+        assert!(store
+            .set_password_if_changed("BitwardenTest", "BitwardenTest", "Other")
+            .unwrap());
+        assert_eq!(
+            "Other",
+            *store
+                .get_password("BitwardenTest", "BitwardenTest")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let store = MemoryStore::new();
+        assert!(!store
+            .compare_and_swap("BitwardenTest", "BitwardenTest", Some("Random"), "New")
+            .unwrap());
+        assert!(store
+            .compare_and_swap("BitwardenTest", "BitwardenTest", None, "Random")
+            .unwrap());
+        assert!(!store
+            .compare_and_swap("BitwardenTest", "BitwardenTest", None, "New")
+            .unwrap());
+        assert!(!store
+            .compare_and_swap("BitwardenTest", "BitwardenTest", Some("Other"), "New")
+            .unwrap());
+        assert!(store
+            .compare_and_swap("BitwardenTest", "BitwardenTest", Some("Random"), "New")
+            .unwrap());
+        assert_eq!(
+            "New",
+            *store
+                .get_password("BitwardenTest", "BitwardenTest")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_entry_metadata() {
+        let store = MemoryStore::new();
+        let metadata = EntryMetadata {
+            label: Some("Biometric unlock".to_owned()),
+            comment: Some("Configured on this device".to_owned()),
+        };
+        store
+            .set_password_with_metadata("BitwardenTest", "BitwardenTest", "Random", &metadata)
+            .unwrap();
+
+        let entry = store.get_entry("BitwardenTest", "BitwardenTest").unwrap();
+        assert_eq!(*entry.password, "Random");
+        assert_eq!(entry.label, metadata.label);
+        assert_eq!(entry.comment, metadata.comment);
+        let created = entry.created.unwrap();
+        assert_eq!(entry.modified, Some(created));
+
+        // Updating keeps the creation time but replaces the metadata
+        store
+            .set_password("BitwardenTest", "BitwardenTest", "Other")
+            .unwrap();
+        let entry = store.get_entry("BitwardenTest", "BitwardenTest").unwrap();
+        assert_eq!(*entry.password, "Other");
+        assert_eq!(entry.created, Some(created));
+        assert!(entry.modified.unwrap() >= created);
+        assert_eq!(entry.label, None);
+        assert_eq!(entry.comment, None);
+    }
+
+    #[test]
+    fn test_swap_keeps_metadata() {
+        let store = MemoryStore::new();
+        let metadata = EntryMetadata {
+            label: Some("Biometric unlock".to_owned()),
+            comment: Some("Configured on this device".to_owned()),
+        };
+        store
+            .set_password_with_metadata("BitwardenTest", "BitwardenTest", "Random", &metadata)
+            .unwrap();
+
+        assert!(store
+            .compare_and_swap("BitwardenTest", "BitwardenTest", Some("Random"), "New")
+            .unwrap());
+        assert!(store
+            .set_password_if_changed("BitwardenTest", "BitwardenTest", "Other")
+            .unwrap());
+
+        let entry = store.get_entry("BitwardenTest", "BitwardenTest").unwrap();
+        assert_eq!(*entry.password, "Other");
+        assert_eq!(entry.label, metadata.label);
+        assert_eq!(entry.comment, metadata.comment);
+    }
+
+    #[test]
+    fn test_delete_all() {
+        let store = MemoryStore::new();
+        store.set_password("BitwardenTest", "a", "Random").unwrap();
+        store
+            .set_password("BitwardenTest_biometric", "a", "Random")
+            .unwrap();
+        store.set_password("Other", "a", "Random").unwrap();
+
+        assert_eq!(store.delete_all("BitwardenTest").unwrap(), 2);
+        assert_eq!(store.list_accounts("").unwrap(), [account("Other", "a")]);
+    }
+}
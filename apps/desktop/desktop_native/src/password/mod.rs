@@ -1,5 +1,586 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, SystemTime},
+};
+
+use zeroize::Zeroizing;
+
+use crate::error::KeychainError;
+
 #[cfg_attr(target_os = "linux", path = "unix.rs")]
 #[cfg_attr(target_os = "windows", path = "windows.rs")]
 #[cfg_attr(target_os = "macos", path = "macos.rs")]
-mod password;
-pub use password::*;
+mod os;
+
+#[cfg(any(windows, test))]
+mod chunked;
+mod encrypted_file;
+#[cfg(target_os = "linux")]
+mod fallback;
+mod memory;
+#[cfg(any(windows, test))]
+mod utf16;
+
+pub use encrypted_file::EncryptedFileStore;
+#[cfg(target_os = "linux")]
+pub use fallback::SecretServiceStore;
+pub use memory::MemoryStore;
+pub use os::get_password_keytar;
+
+type Result<T> = std::result::Result<T, KeychainError>;
+
+// The entries of the stores that keep everything in one map
+type Entries = BTreeMap<(String, String), StoredSecret>;
+
+// Serializes the conditional writes of stores that can't compare and write in one step. This only
+// covers this process, another process can still write between the read and the write.
+static CONDITIONAL_WRITE: Mutex<()> = Mutex::new(());
+
+/// A place to store passwords, identified by service and account.
+pub trait CredentialStore: Send + Sync {
+    /// Fetch the stored password.
+    fn get_password(&self, service: &str, account: &str) -> Result<Zeroizing<String>> {
+        password_from_bytes(self.get_secret_bytes(service, account)?)
+    }
+    /// Save the password. Adds an entry if none exists otherwise updates the existing entry.
+    fn set_password(&self, service: &str, account: &str, password: &str) -> Result<()> {
+        self.set_secret_bytes(service, account, password.as_bytes())
+    }
+    /// Save the password unless the stored password is already equal, returning whether it was
+    /// written. The label and comment of an existing entry are kept.
+    ///
+    /// The default implementation reads and then writes the entry, holding a lock that only
+    /// orders it against the other conditional writes of this process.
+    fn set_password_if_changed(
+        &self,
+        service: &str,
+        account: &str,
+        password: &str,
+    ) -> Result<bool> {
+        let _guard = CONDITIONAL_WRITE.lock().unwrap_or_else(|e| e.into_inner());
+        let metadata = match self.get_entry(service, account) {
+            Ok(current) if *current.password == password => return Ok(false),
+            Ok(current) => current.metadata(),
+            Err(KeychainError::NotFound) => EntryMetadata::default(),
+            Err(e) => return Err(e),
+        };
+
+        self.set_password_with_metadata(service, account, password, &metadata)?;
+        Ok(true)
+    }
+    /// Save the password only if the stored password equals `expected`, where `None` expects no
+    /// entry to exist. Returns whether it was written. The label and comment of an existing entry
+    /// are kept.
+    ///
+    /// The default implementation reads and then writes the entry, holding a lock that only
+    /// orders it against the other conditional writes of this process.
+    fn compare_and_swap(
+        &self,
+        service: &str,
+        account: &str,
+        expected: Option<&str>,
+        password: &str,
+    ) -> Result<bool> {
+        let _guard = CONDITIONAL_WRITE.lock().unwrap_or_else(|e| e.into_inner());
+        let current = match self.get_entry(service, account) {
+            Ok(current) => Some(current),
+            Err(KeychainError::NotFound) => None,
+            Err(e) => return Err(e),
+        };
+        if current.as_ref().map(|current| current.password.as_str()) != expected {
+            return Ok(false);
+        }
+
+        let metadata = current
+            .map(|current| current.metadata())
+            .unwrap_or_default();
+        self.set_password_with_metadata(service, account, password, &metadata)?;
+        Ok(true)
+    }
+    /// Fetch the stored password along with its metadata. Backends that don't keep some of the
+    /// metadata leave it empty.
+    fn get_entry(&self, service: &str, account: &str) -> Result<Entry> {
+        Ok(Entry {
+            password: self.get_password(service, account)?,
+            ..Default::default()
+        })
+    }
+    /// Save the password with a label and comment, which replace those of an existing entry.
+    /// Backends that can't store them ignore them.
+    fn set_password_with_metadata(
+        &self,
+        service: &str,
+        account: &str,
+        password: &str,
+        _metadata: &EntryMetadata,
+    ) -> Result<()> {
+        self.set_password(service, account, password)
+    }
+    /// Fetch the stored secret as raw bytes.
+    fn get_secret_bytes(&self, service: &str, account: &str) -> Result<Zeroizing<Vec<u8>>>;
+    /// Save the secret as raw bytes. Adds an entry if none exists otherwise updates the existing
+    /// entry.
+    fn set_secret_bytes(&self, service: &str, account: &str, secret: &[u8]) -> Result<()>;
+    /// Delete the stored password.
+    fn delete_password(&self, service: &str, account: &str) -> Result<()>;
+    /// List the accounts stored for every service that starts with the prefix.
+    fn list_accounts(&self, service_prefix: &str) -> Result<Vec<Account>>;
+    /// Delete the passwords of every service that starts with the prefix, returning how many were
+    /// deleted.
+    fn delete_all(&self, service_prefix: &str) -> Result<usize> {
+        let mut deleted = 0;
+        for entry in self.list_accounts(service_prefix)? {
+            match self.delete_password(&entry.service, &entry.account) {
+                Ok(()) => deleted += 1,
+                // Removed concurrently, which is the desired outcome anyway
+                Err(KeychainError::NotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(deleted)
+    }
+    /// Whether the passwords are stored in a fallback rather than a proper keychain, so the UI
+    /// can warn the user.
+    fn uses_fallback(&self) -> bool {
+        false
+    }
+}
+
+/// Identifies a stored password.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Account {
+    pub service: String,
+    pub account: String,
+}
+
+/// A stored password with its metadata.
+#[derive(Default)]
+pub struct Entry {
+    pub password: Zeroizing<String>,
+    pub created: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+    pub label: Option<String>,
+    pub comment: Option<String>,
+}
+
+impl Entry {
+    fn metadata(&self) -> EntryMetadata {
+        EntryMetadata {
+            label: self.label.clone(),
+            comment: self.comment.clone(),
+        }
+    }
+}
+
+/// The metadata that is saved along with a password.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryMetadata {
+    /// Shown by keychain managers, defaults to a name derived from the service and account.
+    pub label: Option<String>,
+    pub comment: Option<String>,
+}
+
+/// The platform keychain: libsecret on Linux, Keychain on macOS and Credential Manager on
+/// Windows.
+pub struct OsStore;
+
+impl CredentialStore for OsStore {
+    fn get_password(&self, service: &str, account: &str) -> Result<Zeroizing<String>> {
+        os::get_password(service, account)
+    }
+
+    fn set_password(&self, service: &str, account: &str, password: &str) -> Result<()> {
+        os::set_password(service, account, password)
+    }
+
+    fn get_entry(&self, service: &str, account: &str) -> Result<Entry> {
+        os::get_entry(service, account)
+    }
+
+    fn set_password_with_metadata(
+        &self,
+        service: &str,
+        account: &str,
+        password: &str,
+        metadata: &EntryMetadata,
+    ) -> Result<()> {
+        os::set_password_with_metadata(service, account, password, metadata)
+    }
+
+    fn get_secret_bytes(&self, service: &str, account: &str) -> Result<Zeroizing<Vec<u8>>> {
+        os::get_secret_bytes(service, account)
+    }
+
+    fn set_secret_bytes(&self, service: &str, account: &str, secret: &[u8]) -> Result<()> {
+        os::set_secret_bytes(service, account, secret)
+    }
+
+    fn delete_password(&self, service: &str, account: &str) -> Result<()> {
+        os::delete_password(service, account)
+    }
+
+    fn list_accounts(&self, service_prefix: &str) -> Result<Vec<Account>> {
+        let mut accounts = os::list_accounts(service_prefix)?;
+        accounts.sort();
+        Ok(accounts)
+    }
+}
+
+// `None` means the default store
+static STORE: RwLock<Option<Arc<dyn CredentialStore>>> = RwLock::new(None);
+
+/// The platform keychain. On Linux it falls back to an encrypted file when no Secret Service
+/// provider is running, and on Windows large secrets are split to fit Credential Manager.
+pub fn default_store() -> Arc<dyn CredentialStore> {
+    #[cfg(target_os = "linux")]
+    return Arc::new(SecretServiceStore::new());
+
+    #[cfg(target_os = "windows")]
+    return Arc::new(chunked::ChunkedStore::new(OsStore, os::MAX_BLOB_SIZE));
+
+    #[cfg(target_os = "macos")]
+    return Arc::new(OsStore);
+}
+
+/// The store used by the free functions in this module.
+pub fn store() -> Arc<dyn CredentialStore> {
+    match STORE.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        Some(store) => store.clone(),
+        None => default_store(),
+    }
+}
+
+/// Replace the store used by the free functions in this module.
+pub fn set_store(store: Arc<dyn CredentialStore>) {
+    *STORE.write().unwrap_or_else(|e| e.into_inner()) = Some(store);
+}
+
+pub fn get_password(service: &str, account: &str) -> Result<Zeroizing<String>> {
+    store().get_password(service, account)
+}
+
+pub fn set_password(service: &str, account: &str, password: &str) -> Result<()> {
+    store().set_password(service, account, password)
+}
+
+/// Fetch the stored password along with when it was created and last modified, its label and its
+/// comment, as far as the backend keeps them.
+pub fn get_entry(service: &str, account: &str) -> Result<Entry> {
+    store().get_entry(service, account)
+}
+
+/// Save the password with a label and comment, which replace those of an existing entry.
+pub fn set_password_with_metadata(
+    service: &str,
+    account: &str,
+    password: &str,
+    metadata: &EntryMetadata,
+) -> Result<()> {
+    store().set_password_with_metadata(service, account, password, metadata)
+}
+
+/// Save the password unless it is already stored, returning whether it was written. The label and
+/// comment of an existing entry are kept.
+///
+/// Unless the store can compare and write in one step, this is a read followed by a write. The
+/// conditional writes of this process don't interleave, but a write by another process in between
+/// is overwritten.
+pub fn set_password_if_changed(service: &str, account: &str, password: &str) -> Result<bool> {
+    store().set_password_if_changed(service, account, password)
+}
+
+/// Save the password only if the stored password equals `expected`, where `None` expects no entry
+/// to exist. Returns whether it was written. Like `set_password_if_changed`, this is only atomic
+/// within this process unless the store can compare and write in one step.
+pub fn compare_and_swap(
+    service: &str,
+    account: &str,
+    expected: Option<&str>,
+    password: &str,
+) -> Result<bool> {
+    store().compare_and_swap(service, account, expected, password)
+}
+
+pub fn get_secret_bytes(service: &str, account: &str) -> Result<Zeroizing<Vec<u8>>> {
+    store().get_secret_bytes(service, account)
+}
+
+pub fn set_secret_bytes(service: &str, account: &str, secret: &[u8]) -> Result<()> {
+    store().set_secret_bytes(service, account, secret)
+}
+
+pub fn delete_password(service: &str, account: &str) -> Result<()> {
+    store().delete_password(service, account)
+}
+
+/// List the accounts of every service that starts with the prefix. An empty prefix is rejected,
+/// since it would match the entries of other applications.
+pub fn list_accounts(service_prefix: &str) -> Result<Vec<Account>> {
+    check_prefix(service_prefix)?;
+    store().list_accounts(service_prefix)
+}
+
+/// Delete the passwords of every service that starts with the prefix. An empty prefix is
+/// rejected, since it would match the entries of other applications.
+pub fn delete_all(service_prefix: &str) -> Result<usize> {
+    check_prefix(service_prefix)?;
+    store().delete_all(service_prefix)
+}
+
+/// Run the keychain operation on the tokio blocking pool, since it can wait on an unlock prompt
+/// indefinitely. Fails with `Timeout` or `Cancelled` when the timeout elapses or `cancelled`
+/// completes first. The operation itself can't be interrupted, it is only skipped when it hasn't
+/// started yet.
+pub async fn run_blocking<T, F>(
+    operation: F,
+    timeout: Option<Duration>,
+    cancelled: impl Future<Output = ()>,
+) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    tokio::pin!(cancelled);
+
+    // Don't start operations that were cancelled before they were scheduled
+    tokio::select! {
+        biased;
+        _ = &mut cancelled => return Err(KeychainError::Cancelled),
+        _ = std::future::ready(()) => {}
+    }
+
+    let abandoned = Arc::new(AtomicBool::new(false));
+    let task = tokio::task::spawn_blocking({
+        let abandoned = abandoned.clone();
+        move || match abandoned.load(Ordering::Acquire) {
+            true => Err(KeychainError::Cancelled),
+            false => operation(),
+        }
+    });
+    let timeout = async {
+        match timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let result = tokio::select! {
+        result = task => {
+            return result.unwrap_or_else(|e| Err(KeychainError::Other(e.to_string())));
+        }
+        _ = timeout => Err(KeychainError::Timeout),
+        _ = cancelled => Err(KeychainError::Cancelled),
+    };
+    abandoned.store(true, Ordering::Release);
+    result
+}
+
+// A secret with its metadata, as kept by the stores that hold everything in one map
+struct StoredSecret {
+    secret: Zeroizing<Vec<u8>>,
+    // Unknown for entries written before timestamps were kept
+    created: Option<SystemTime>,
+    modified: Option<SystemTime>,
+    metadata: EntryMetadata,
+}
+
+impl StoredSecret {
+    fn to_entry(&self) -> Result<Entry> {
+        Ok(Entry {
+            password: password_from_bytes(self.secret.clone())?,
+            created: self.created,
+            modified: self.modified,
+            label: self.metadata.label.clone(),
+            comment: self.metadata.comment.clone(),
+        })
+    }
+}
+
+// Add or replace the secret, keeping the creation time of the replaced entry
+fn insert_entry(
+    entries: &mut Entries,
+    service: &str,
+    account: &str,
+    secret: &[u8],
+    metadata: &EntryMetadata,
+) {
+    let key = (service.to_owned(), account.to_owned());
+    let now = SystemTime::now();
+    let created = match entries.get(&key) {
+        Some(current) => current.created,
+        None => Some(now),
+    };
+
+    entries.insert(
+        key,
+        StoredSecret {
+            secret: Zeroizing::new(secret.to_vec()),
+            created,
+            modified: Some(now),
+            metadata: metadata.clone(),
+        },
+    );
+}
+
+// Store the secret if `matches` accepts the current secret, returning whether it was stored. The
+// metadata of the current entry is kept.
+fn swap_entry(
+    entries: &mut Entries,
+    service: &str,
+    account: &str,
+    matches: impl FnOnce(Option<&[u8]>) -> bool,
+    secret: &[u8],
+) -> bool {
+    let current = entries.get(&(service.to_owned(), account.to_owned()));
+    if !matches(current.map(|current| current.secret.as_slice())) {
+        return false;
+    }
+
+    let metadata = current
+        .map(|current| current.metadata.clone())
+        .unwrap_or_default();
+    insert_entry(entries, service, account, secret, &metadata);
+    true
+}
+
+fn password_from_bytes(mut bytes: Zeroizing<Vec<u8>>) -> Result<Zeroizing<String>> {
+    match String::from_utf8(std::mem::take(&mut *bytes)) {
+        Ok(password) => Ok(Zeroizing::new(password)),
+        Err(e) => {
+            // Wipe the rejected bytes as well
+            drop(Zeroizing::new(e.into_bytes()));
+            Err(KeychainError::InvalidData(
+                "the secret is not valid UTF-8".to_owned(),
+            ))
+        }
+    }
+}
+
+fn check_prefix(service_prefix: &str) -> Result<()> {
+    match service_prefix.is_empty() {
+        true => Err(KeychainError::InvalidData(
+            "the service prefix must not be empty".to_owned(),
+        )),
+        false => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::SymmetricCryptoKey;
+
+    #[test]
+    fn test_error_empty_prefix() {
+        assert!(matches!(
+            list_accounts(""),
+            Err(KeychainError::InvalidData(_))
+        ));
+        assert!(matches!(delete_all(""), Err(KeychainError::InvalidData(_))));
+    }
+
+    #[test]
+    fn test_error_delete_missing_entry() {
+        let path = std::env::temp_dir().join(format!(
+            "bitwarden-delete-missing-{}.dat",
+            std::process::id()
+        ));
+        scopeguard::defer!(let _ = std::fs::remove_file(&path););
+        let key: Vec<u8> = (0..64).collect();
+        let key = SymmetricCryptoKey::try_from(key.as_slice()).unwrap();
+
+        // Every backend reports the same error for the same situation
+        let stores: Vec<Arc<dyn CredentialStore>> = vec![
+            Arc::new(MemoryStore::new()),
+            Arc::new(EncryptedFileStore::new(&path, key).unwrap()),
+            Arc::new(OsStore),
+            default_store(),
+        ];
+        for store in stores {
+            assert!(matches!(
+                store.delete_password("BitwardenTest", "BitwardenTestMissing"),
+                Err(KeychainError::NotFound)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_blocking() {
+        let result = run_blocking(|| Ok(1), None, std::future::pending()).await;
+        assert_eq!(result.unwrap(), 1);
+
+        let result = run_blocking(
+            || Err::<(), _>(KeychainError::NotFound),
+            Some(Duration::from_secs(10)),
+            std::future::pending(),
+        )
+        .await;
+        assert!(matches!(result, Err(KeychainError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_run_blocking_timeout() {
+        let result = run_blocking(
+            || {
+                std::thread::sleep(Duration::from_millis(500));
+                Ok(())
+            },
+            Some(Duration::from_millis(10)),
+            std::future::pending(),
+        )
+        .await;
+        assert!(matches!(result, Err(KeychainError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn test_run_blocking_cancelled() {
+        let (started, has_started) = tokio::sync::oneshot::channel();
+        let (finish, wait_for_finish) = std::sync::mpsc::channel::<()>();
+        let (cancel, cancelled) = tokio::sync::oneshot::channel();
+        let finished = Arc::new(AtomicBool::new(false));
+        let task = tokio::spawn(run_blocking(
+            {
+                let finished = finished.clone();
+                move || {
+                    started.send(()).unwrap();
+                    let _ = wait_for_finish.recv();
+                    finished.store(true, Ordering::Release);
+                    Ok(())
+                }
+            },
+            None,
+            async {
+                let _ = cancelled.await;
+            },
+        ));
+
+        // Cancelled while the operation is blocked, which it doesn't wait for
+        has_started.await.unwrap();
+        cancel.send(()).unwrap();
+        assert!(matches!(task.await.unwrap(), Err(KeychainError::Cancelled)));
+        assert!(!finished.load(Ordering::Acquire));
+        finish.send(()).unwrap();
+
+        // Operations cancelled up front never run
+        let ran = Arc::new(AtomicBool::new(false));
+        let result = run_blocking(
+            {
+                let ran = ran.clone();
+                move || {
+                    ran.store(true, Ordering::Release);
+                    Ok(())
+                }
+            },
+            None,
+            std::future::ready(()),
+        )
+        .await;
+        assert!(matches!(result, Err(KeychainError::Cancelled)));
+        assert!(!ran.load(Ordering::Acquire));
+    }
+}
@@ -1,55 +1,295 @@
-use anyhow::{anyhow, Result};
-use libsecret::{password_clear_sync, password_lookup_sync, password_store_sync, Schema};
-use std::collections::HashMap;
+use gio::glib::{self, error::ErrorDomain, translate::from_glib_full};
+use libsecret::{
+    password_clear_sync, password_lookup_binary_sync, password_lookup_sync, password_search_sync,
+    password_store_binary_sync, password_store_sync,
+    prelude::{RetrievableExt, RetrievableExtManual},
+    Schema, SearchFlags, Value,
+};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    path::PathBuf,
+    time::{Duration, UNIX_EPOCH},
+};
+use zeroize::Zeroizing;
 
-pub fn get_password(service: &str, account: &str) -> Result<String> {
-    let res = password_lookup_sync(
-        Some(&get_schema()),
-        build_attributes(service, account),
-        gio::Cancellable::NONE,
-    )?;
+use super::{Account, Entry, EntryMetadata};
+use crate::error::KeychainError;
 
-    match res {
-        Some(s) => Ok(String::from(s)),
-        None => Err(anyhow!("No password found")),
-    }
+type Result<T> = std::result::Result<T, KeychainError>;
+
+const BINARY_CONTENT_TYPE: &[u8] = b"application/octet-stream\0";
+
+const SCHEMA_NAME: &str = "com.bitwarden.Desktop";
+// Used by older versions, entries are moved to the Bitwarden schema when they are read
+const LEGACY_SCHEMA_NAME: &str = "org.freedesktop.Secret.Generic";
+// Stored in the `version` attribute to allow changing the format of the entries later
+const FORMAT_VERSION: &str = "1";
+
+const KIND_PASSWORD: &str = "password";
+const KIND_SECRET: &str = "secret";
+
+pub fn get_password(service: &str, account: &str) -> Result<Zeroizing<String>> {
+    lookup(
+        service,
+        account,
+        |schema| {
+            password_lookup_sync(
+                Some(schema),
+                build_attributes(service, account),
+                gio::Cancellable::NONE,
+            )
+            .map(|res| res.map(|s| Zeroizing::new(String::from(s))))
+        },
+        |password| set_password(service, account, password),
+    )
 }
 
-pub fn get_password_keytar(service: &str, account: &str) -> Result<String> {
+pub fn get_password_keytar(service: &str, account: &str) -> Result<Zeroizing<String>> {
     get_password(service, account)
 }
 
 pub fn set_password(service: &str, account: &str, password: &str) -> Result<()> {
-    let result = password_store_sync(
+    set_password_with_metadata(service, account, password, &EntryMetadata::default())
+}
+
+pub fn get_entry(service: &str, account: &str) -> Result<Entry> {
+    // Reading the password first moves legacy entries to the Bitwarden schema
+    let password = get_password(service, account)?;
+
+    let items = password_search_sync(
         Some(&get_schema()),
         build_attributes(service, account),
+        SearchFlags::NONE,
+        gio::Cancellable::NONE,
+    )
+    .map_err(convert_error)?;
+
+    // Legacy entries that couldn't be migrated have no metadata
+    let Some(item) = items.first() else {
+        return Ok(Entry {
+            password,
+            ..Default::default()
+        });
+    };
+
+    Ok(Entry {
+        password,
+        created: Some(UNIX_EPOCH + Duration::from_secs(item.created())),
+        modified: Some(UNIX_EPOCH + Duration::from_secs(item.modified())),
+        label: Some(item.label().into()),
+        comment: item.attributes().remove("comment"),
+    })
+}
+
+pub fn set_password_with_metadata(
+    service: &str,
+    account: &str,
+    password: &str,
+    metadata: &EntryMetadata,
+) -> Result<()> {
+    let mut attributes = store_attributes(service, account, KIND_PASSWORD);
+    if let Some(comment) = &metadata.comment {
+        attributes.insert("comment", comment);
+    }
+
+    password_store_sync(
+        Some(&get_schema()),
+        attributes.clone(),
         Some(&libsecret::COLLECTION_DEFAULT),
-        &format!("{}/{}", service, account),
+        &metadata
+            .label
+            .clone()
+            .unwrap_or_else(|| label(service, account)),
         password,
         gio::Cancellable::NONE,
-    )?;
-    Ok(result)
+    )
+    .map_err(convert_error)?;
+
+    clear_replaced(service, account, &attributes)
+}
+
+pub fn get_secret_bytes(service: &str, account: &str) -> Result<Zeroizing<Vec<u8>>> {
+    lookup(
+        service,
+        account,
+        |schema| {
+            password_lookup_binary_sync(
+                Some(schema),
+                build_attributes(service, account),
+                gio::Cancellable::NONE,
+            )
+            .map(|res| res.map(|value| Zeroizing::new(value.get())))
+        },
+        |secret| set_secret_bytes(service, account, secret),
+    )
+}
+
+pub fn set_secret_bytes(service: &str, account: &str, secret: &[u8]) -> Result<()> {
+    // `Value::new` only accepts UTF-8 text, while the C API copies arbitrary bytes
+    let value: Value = unsafe {
+        from_glib_full(libsecret::ffi::secret_value_new(
+            secret.as_ptr() as *const _,
+            secret.len() as _,
+            BINARY_CONTENT_TYPE.as_ptr() as *const _,
+        ))
+    };
+
+    let attributes = store_attributes(service, account, KIND_SECRET);
+    password_store_binary_sync(
+        Some(&get_schema()),
+        attributes.clone(),
+        Some(&libsecret::COLLECTION_DEFAULT),
+        &label(service, account),
+        &value,
+        gio::Cancellable::NONE,
+    )
+    .map_err(convert_error)?;
+
+    clear_replaced(service, account, &attributes)
 }
 
 pub fn delete_password(service: &str, account: &str) -> Result<()> {
-    let result = password_clear_sync(
+    // Clearing succeeds when nothing matched, so look for the entry first to report it missing
+    if !exists(&get_schema(), service, account)? && !exists(&get_legacy_schema(), service, account)?
+    {
+        return Err(KeychainError::NotFound);
+    }
+
+    clear(&get_schema(), service, account)?;
+    clear(&get_legacy_schema(), service, account)
+}
+
+pub fn list_accounts(service_prefix: &str) -> Result<Vec<Account>> {
+    let mut accounts = Vec::new();
+    for schema in [get_schema(), get_legacy_schema()] {
+        // Attribute searches only match exact values, so load every entry of the schema and filter
+        let items = password_search_sync(
+            Some(&schema),
+            HashMap::new(),
+            SearchFlags::ALL,
+            gio::Cancellable::NONE,
+        )
+        .map_err(convert_error)?;
+
+        accounts.extend(
+            items
+                .iter()
+                .filter_map(|item| {
+                    let mut attributes = item.attributes();
+                    Some(Account {
+                        service: attributes.remove("service")?,
+                        account: attributes.remove("account")?,
+                    })
+                })
+                .filter(|entry| entry.service.starts_with(service_prefix)),
+        );
+    }
+
+    // Entries whose migration failed exist in both schemas
+    accounts.sort();
+    accounts.dedup();
+    Ok(accounts)
+}
+
+// Look up the entry with the Bitwarden schema, falling back to the legacy schema. Legacy entries
+// are stored again with `migrate` and only removed once that succeeded.
+fn lookup<T>(
+    service: &str,
+    account: &str,
+    find: impl Fn(&Schema) -> std::result::Result<Option<T>, glib::Error>,
+    migrate: impl FnOnce(&T) -> Result<()>,
+) -> Result<T> {
+    if let Some(value) = find(&get_schema()).map_err(convert_error)? {
+        return Ok(value);
+    }
+
+    let value = find(&get_legacy_schema())
+        .map_err(convert_error)?
+        .ok_or(KeychainError::NotFound)?;
+
+    // The legacy entry stays readable when the migration fails, so it is retried on the next read
+    if migrate(&value).is_ok() {
+        let _ = clear(&get_legacy_schema(), service, account);
+    }
+    Ok(value)
+}
+
+fn exists(schema: &Schema, service: &str, account: &str) -> Result<bool> {
+    let items = password_search_sync(
+        Some(schema),
+        build_attributes(service, account),
+        SearchFlags::NONE,
+        gio::Cancellable::NONE,
+    )
+    .map_err(convert_error)?;
+    Ok(!items.is_empty())
+}
+
+// Storing only updates an item whose attributes all match, so remove the items of the entry that
+// were stored with other attributes, e.g. another comment
+fn clear_replaced(service: &str, account: &str, stored: &HashMap<&str, &str>) -> Result<()> {
+    let items = password_search_sync(
         Some(&get_schema()),
         build_attributes(service, account),
+        SearchFlags::ALL,
+        gio::Cancellable::NONE,
+    )
+    .map_err(convert_error)?;
+
+    for item in items {
+        // The Secret Service adds `xdg:schema`, which isn't part of the schema itself
+        let attributes: HashMap<String, String> = item
+            .attributes()
+            .into_iter()
+            .filter(|(name, _)| !name.starts_with("xdg:"))
+            .collect();
+        let replaced = attributes.len() != stored.len()
+            || attributes
+                .iter()
+                .any(|(name, value)| stored.get(name.as_str()) != Some(&value.as_str()));
+        if replaced {
+            password_clear_sync(
+                Some(&get_schema()),
+                attributes
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str()))
+                    .collect(),
+                gio::Cancellable::NONE,
+            )
+            .map_err(convert_error)?;
+        }
+    }
+    Ok(())
+}
+
+fn clear(schema: &Schema, service: &str, account: &str) -> Result<()> {
+    password_clear_sync(
+        Some(schema),
+        build_attributes(service, account),
         gio::Cancellable::NONE,
-    )?;
-    Ok(result)
+    )
+    .map_err(convert_error)
 }
 
 fn get_schema() -> Schema {
     let mut attributes = std::collections::HashMap::new();
     attributes.insert("service", libsecret::SchemaAttributeType::String);
     attributes.insert("account", libsecret::SchemaAttributeType::String);
+    attributes.insert("user_id", libsecret::SchemaAttributeType::String);
+    attributes.insert("kind", libsecret::SchemaAttributeType::String);
+    attributes.insert("comment", libsecret::SchemaAttributeType::String);
+    attributes.insert("version", libsecret::SchemaAttributeType::Integer);
 
-    libsecret::Schema::new(
-        "org.freedesktop.Secret.Generic",
-        libsecret::SchemaFlags::NONE,
-        attributes,
-    )
+    libsecret::Schema::new(SCHEMA_NAME, libsecret::SchemaFlags::NONE, attributes)
+}
+
+fn get_legacy_schema() -> Schema {
+    let mut attributes = std::collections::HashMap::new();
+    attributes.insert("service", libsecret::SchemaAttributeType::String);
+    attributes.insert("account", libsecret::SchemaAttributeType::String);
+
+    libsecret::Schema::new(LEGACY_SCHEMA_NAME, libsecret::SchemaFlags::NONE, attributes)
 }
 
 fn build_attributes<'a>(service: &'a str, account: &'a str) -> HashMap<&'a str, &'a str> {
@@ -60,6 +300,186 @@ fn build_attributes<'a>(service: &'a str, account: &'a str) -> HashMap<&'a str,
     attributes
 }
 
+// The attributes of a stored entry, which allow searching by user and kind, e.g. in Seahorse
+fn store_attributes<'a>(
+    service: &'a str,
+    account: &'a str,
+    kind: &'static str,
+) -> HashMap<&'a str, &'a str> {
+    let mut attributes = build_attributes(service, account);
+    attributes.insert("kind", kind);
+    attributes.insert("version", FORMAT_VERSION);
+    if let Some(user_id) = user_id(account) {
+        attributes.insert("user_id", user_id);
+    }
+
+    attributes
+}
+
+// Shown by keyring managers instead of the attributes
+fn label(service: &str, account: &str) -> String {
+    match user_id(account) {
+        Some(user_id) => format!("Bitwarden {} ({}) for user {}", service, account, user_id),
+        None => format!("Bitwarden {} ({})", service, account),
+    }
+}
+
+// The desktop app names its accounts `<user id>_<key>`
+fn user_id(account: &str) -> Option<&str> {
+    let (user_id, _) = account.split_once('_')?;
+    let is_uuid = user_id.len() == 36
+        && user_id.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        });
+
+    is_uuid.then_some(user_id)
+}
+
+// Convert the GLib errors raised by libsecret and the D-Bus connection to a `KeychainError`
+fn convert_error(error: glib::Error) -> KeychainError {
+    let has_session_bus = session_bus_configured(
+        std::env::var_os("DBUS_SESSION_BUS_ADDRESS"),
+        std::env::var_os("XDG_RUNTIME_DIR"),
+    );
+    convert_bus_error(error, has_session_bus)
+}
+
+fn convert_bus_error(error: glib::Error, has_session_bus: bool) -> KeychainError {
+    if let Some(kind) = error.kind::<SecretError>() {
+        return match kind {
+            SecretError::IsLocked => KeychainError::Locked,
+            SecretError::NoSuchObject => KeychainError::NotFound,
+            SecretError::Protocol | SecretError::InvalidFileFormat => {
+                KeychainError::InvalidData(error.to_string())
+            }
+            SecretError::AlreadyExists => KeychainError::Other(error.to_string()),
+        };
+    }
+
+    // Only a missing provider is reported as unavailable, since that makes the store switch to the
+    // fallback for the rest of the process. A slow or restarting provider is still there
+    if let Some(kind) = error.kind::<DBusError>() {
+        return match kind {
+            DBusError::AccessDenied | DBusError::AuthFailed => KeychainError::AccessDenied,
+            DBusError::ServiceUnknown | DBusError::NameHasNoOwner | DBusError::NoServer => {
+                KeychainError::BackendUnavailable(error.to_string())
+            }
+            DBusError::NoReply | DBusError::Timeout | DBusError::TimedOut => KeychainError::Timeout,
+            _ => KeychainError::Other(error.to_string()),
+        };
+    }
+
+    // Missing entries are reported as an empty lookup, so an IO not found error means the D-Bus
+    // socket itself is missing. Without a session bus, e.g. on a headless server, gio tries to
+    // autolaunch one, which fails with a generic error
+    match error.kind::<gio::IOErrorEnum>() {
+        Some(gio::IOErrorEnum::PermissionDenied) => KeychainError::AccessDenied,
+        Some(gio::IOErrorEnum::NotFound | gio::IOErrorEnum::ConnectionRefused) => {
+            KeychainError::BackendUnavailable(error.to_string())
+        }
+        Some(gio::IOErrorEnum::Failed) if !has_session_bus => {
+            KeychainError::BackendUnavailable(error.to_string())
+        }
+        Some(gio::IOErrorEnum::TimedOut) => KeychainError::Timeout,
+        _ => KeychainError::Other(error.to_string()),
+    }
+}
+
+// Whether gio finds a session bus without autolaunching one, i.e. the bus address is set or the
+// bus socket is in its default place
+fn session_bus_configured(address: Option<OsString>, runtime_dir: Option<OsString>) -> bool {
+    address.is_some()
+        || runtime_dir.is_some_and(|runtime_dir| PathBuf::from(runtime_dir).join("bus").exists())
+}
+
+/// `SecretError` from libsecret, the bindings don't implement `ErrorDomain` for it.
+#[derive(Clone, Copy)]
+enum SecretError {
+    Protocol,
+    IsLocked,
+    NoSuchObject,
+    AlreadyExists,
+    InvalidFileFormat,
+}
+
+impl ErrorDomain for SecretError {
+    fn domain() -> glib::Quark {
+        glib::Quark::from_str("secret-error")
+    }
+
+    fn code(self) -> i32 {
+        match self {
+            SecretError::Protocol => 1,
+            SecretError::IsLocked => 2,
+            SecretError::NoSuchObject => 3,
+            SecretError::AlreadyExists => 4,
+            SecretError::InvalidFileFormat => 5,
+        }
+    }
+
+    fn from(code: i32) -> Option<Self> {
+        match code {
+            1 => Some(SecretError::Protocol),
+            2 => Some(SecretError::IsLocked),
+            3 => Some(SecretError::NoSuchObject),
+            4 => Some(SecretError::AlreadyExists),
+            5 => Some(SecretError::InvalidFileFormat),
+            _ => None,
+        }
+    }
+}
+
+/// The subset of `GDBusError` that is relevant for talking to the Secret Service, which gio
+/// doesn't expose.
+#[derive(Clone, Copy)]
+enum DBusError {
+    ServiceUnknown,
+    NameHasNoOwner,
+    NoReply,
+    AccessDenied,
+    AuthFailed,
+    NoServer,
+    Timeout,
+    Disconnected,
+    TimedOut,
+}
+
+impl ErrorDomain for DBusError {
+    fn domain() -> glib::Quark {
+        glib::Quark::from_str("g-dbus-error-quark")
+    }
+
+    fn code(self) -> i32 {
+        match self {
+            DBusError::ServiceUnknown => 2,
+            DBusError::NameHasNoOwner => 3,
+            DBusError::NoReply => 4,
+            DBusError::AccessDenied => 9,
+            DBusError::AuthFailed => 10,
+            DBusError::NoServer => 11,
+            DBusError::Timeout => 12,
+            DBusError::Disconnected => 15,
+            DBusError::TimedOut => 20,
+        }
+    }
+
+    fn from(code: i32) -> Option<Self> {
+        match code {
+            2 => Some(DBusError::ServiceUnknown),
+            3 => Some(DBusError::NameHasNoOwner),
+            4 => Some(DBusError::NoReply),
+            9 => Some(DBusError::AccessDenied),
+            10 => Some(DBusError::AuthFailed),
+            11 => Some(DBusError::NoServer),
+            12 => Some(DBusError::Timeout),
+            15 => Some(DBusError::Disconnected),
+            20 => Some(DBusError::TimedOut),
+            _ => None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -70,22 +490,181 @@ mod tests {
         set_password("BitwardenTest", "BitwardenTest", "Random").unwrap();
         assert_eq!(
             "Random",
-            get_password("BitwardenTest", "BitwardenTest").unwrap()
+            *get_password("BitwardenTest", "BitwardenTest").unwrap()
         );
         delete_password("BitwardenTest", "BitwardenTest").unwrap();
 
         // Ensure password is deleted
         match get_password("BitwardenTest", "BitwardenTest") {
             Ok(_) => panic!("Got a result"),
-            Err(e) => assert_eq!("No password found", e.to_string()),
+            Err(e) => assert!(matches!(e, KeychainError::NotFound)),
         }
     }
 
+    #[test]
+    fn test_secret_bytes() {
+        scopeguard::defer!(delete_password("BitwardenTest", "BitwardenTest").unwrap_or({}););
+        let secret = [0u8, 159, 146, 150, 255];
+        set_secret_bytes("BitwardenTest", "BitwardenTest", &secret).unwrap();
+        assert_eq!(
+            *get_secret_bytes("BitwardenTest", "BitwardenTest").unwrap(),
+            secret
+        );
+    }
+
+    #[test]
+    fn test_entry_metadata() {
+        scopeguard::defer!(delete_password("BitwardenTest", "BitwardenTest").unwrap_or({}););
+        let metadata = EntryMetadata {
+            label: Some("Biometric unlock".to_owned()),
+            comment: Some("Configured on this device".to_owned()),
+        };
+        set_password_with_metadata("BitwardenTest", "BitwardenTest", "Random", &metadata).unwrap();
+
+        let entry = get_entry("BitwardenTest", "BitwardenTest").unwrap();
+        assert_eq!(*entry.password, "Random");
+        assert_eq!(entry.label, metadata.label);
+        assert_eq!(entry.comment, metadata.comment);
+        assert!(entry.created.is_some());
+        assert!(entry.modified.is_some());
+
+        // Replaces the metadata, rather than adding an item with other attributes
+        set_password("BitwardenTest", "BitwardenTest", "Other").unwrap();
+        let entry = get_entry("BitwardenTest", "BitwardenTest").unwrap();
+        assert_eq!(*entry.password, "Other");
+        assert_eq!(entry.label, Some(label("BitwardenTest", "BitwardenTest")));
+        assert_eq!(entry.comment, None);
+        assert!(!exists_with_comment("Configured on this device"));
+    }
+
+    fn exists_with_comment(comment: &str) -> bool {
+        let mut attributes = build_attributes("BitwardenTest", "BitwardenTest");
+        attributes.insert("comment", comment);
+        !password_search_sync(
+            Some(&get_schema()),
+            attributes,
+            SearchFlags::ALL,
+            gio::Cancellable::NONE,
+        )
+        .unwrap()
+        .is_empty()
+    }
+
+    #[test]
+    fn test_migrate_legacy_entry() {
+        scopeguard::defer!(delete_password("BitwardenTest", "BitwardenTest").unwrap_or({}););
+        password_store_sync(
+            Some(&get_legacy_schema()),
+            build_attributes("BitwardenTest", "BitwardenTest"),
+            Some(&libsecret::COLLECTION_DEFAULT),
+            "BitwardenTest/BitwardenTest",
+            "Random",
+            gio::Cancellable::NONE,
+        )
+        .unwrap();
+
+        assert_eq!(
+            "Random",
+            *get_password("BitwardenTest", "BitwardenTest").unwrap()
+        );
+
+        // Moved to the Bitwarden schema
+        let lookup = |schema: &Schema| {
+            password_lookup_sync(
+                Some(schema),
+                build_attributes("BitwardenTest", "BitwardenTest"),
+                gio::Cancellable::NONE,
+            )
+            .unwrap()
+        };
+        assert!(lookup(&get_legacy_schema()).is_none());
+        assert_eq!(lookup(&get_schema()).unwrap(), "Random");
+    }
+
+    #[test]
+    fn test_user_id() {
+        assert_eq!(
+            user_id("4c7a2b8e-1f3d-4e5a-9b6c-0d1e2f3a4b5c_user_biometric"),
+            Some("4c7a2b8e-1f3d-4e5a-9b6c-0d1e2f3a4b5c")
+        );
+        assert_eq!(user_id("BitwardenTest"), None);
+        assert_eq!(user_id("not-a-user-id_biometric"), None);
+    }
+
+    #[test]
+    fn test_convert_error() {
+        let error = |kind: DBusError| convert_error(glib::Error::new(kind, "D-Bus error"));
+
+        assert!(matches!(
+            error(DBusError::ServiceUnknown),
+            KeychainError::BackendUnavailable(_)
+        ));
+        assert!(matches!(error(DBusError::NoReply), KeychainError::Timeout));
+        assert!(matches!(
+            error(DBusError::Disconnected),
+            KeychainError::Other(_)
+        ));
+        assert!(matches!(
+            error(DBusError::NoServer),
+            KeychainError::BackendUnavailable(_)
+        ));
+    }
+
+    #[test]
+    fn test_convert_error_without_session_bus() {
+        let autolaunch = || {
+            glib::Error::new(
+                gio::IOErrorEnum::Failed,
+                "Cannot autolaunch D-Bus without X11 $DISPLAY",
+            )
+        };
+
+        assert!(matches!(
+            convert_bus_error(autolaunch(), false),
+            KeychainError::BackendUnavailable(_)
+        ));
+        assert!(matches!(
+            convert_bus_error(autolaunch(), true),
+            KeychainError::Other(_)
+        ));
+        assert!(matches!(
+            convert_bus_error(
+                glib::Error::new(gio::IOErrorEnum::ConnectionRefused, "Connection refused"),
+                true
+            ),
+            KeychainError::BackendUnavailable(_)
+        ));
+    }
+
+    #[test]
+    fn test_session_bus_configured() {
+        let runtime_dir =
+            std::env::temp_dir().join(format!("bitwarden-bus-{}", std::process::id()));
+        std::fs::create_dir_all(&runtime_dir).unwrap();
+        scopeguard::defer!(let _ = std::fs::remove_dir_all(&runtime_dir););
+
+        assert!(session_bus_configured(
+            Some("unix:path=/run/bus".into()),
+            None
+        ));
+        assert!(!session_bus_configured(None, None));
+        assert!(!session_bus_configured(
+            None,
+            Some(runtime_dir.clone().into())
+        ));
+
+        std::fs::write(runtime_dir.join("bus"), "").unwrap();
+        assert!(session_bus_configured(
+            None,
+            Some(runtime_dir.clone().into())
+        ));
+    }
+
     #[test]
     fn test_error_no_password() {
         match get_password("BitwardenTest", "BitwardenTest") {
             Ok(_) => panic!("Got a result"),
-            Err(e) => assert_eq!("No password found", e.to_string()),
+            Err(e) => assert!(matches!(e, KeychainError::NotFound)),
         }
     }
 }
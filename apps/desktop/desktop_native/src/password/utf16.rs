@@ -0,0 +1,134 @@
+//! Encoding of passwords in Windows credential blobs, as UTF-16LE without a NUL terminator.
+//!
+//! Older versions sized the blob by the UTF-8 length of the password rather than its UTF-16
+//! length, so entries with non-ASCII characters contain the password, a NUL terminator and
+//! whatever followed it in memory. Those are detected when decoding so they can be rewritten.
+
+use zeroize::Zeroizing;
+
+use super::Result;
+use crate::error::KeychainError;
+
+/// A password read from a credential blob.
+pub struct Decoded {
+    pub password: Zeroizing<String>,
+    /// The blob was written in the old format and should be rewritten.
+    pub needs_repair: bool,
+}
+
+/// Encode the password as UTF-16LE. Passwords containing NUL are rejected, as they were before,
+/// which keeps old format blobs unambiguous.
+pub fn encode(password: &str) -> Result<Zeroizing<Vec<u8>>> {
+    if password.contains('\0') {
+        return Err(KeychainError::InvalidData(
+            "the password contains a NUL character".to_owned(),
+        ));
+    }
+
+    let mut blob = Zeroizing::new(Vec::with_capacity(password.len() * 2));
+    for unit in password.encode_utf16() {
+        blob.extend_from_slice(&unit.to_le_bytes());
+    }
+    Ok(blob)
+}
+
+pub fn decode(blob: &[u8]) -> Result<Decoded> {
+    let chunks = blob.chunks_exact(2);
+    if !chunks.remainder().is_empty() {
+        return Err(invalid_data());
+    }
+
+    let units: Zeroizing<Vec<u16>> = Zeroizing::new(
+        chunks
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .collect(),
+    );
+
+    let (units, needs_repair) = match units.iter().position(|unit| *unit == 0) {
+        Some(end) => (&units[..end], true),
+        None => (&units[..], false),
+    };
+
+    let password = Zeroizing::new(String::from_utf16(units).map_err(|_| invalid_data())?);
+
+    // The old format is exactly twice the UTF-8 length, anything else containing a NUL wasn't
+    // written by us
+    if needs_repair && blob.len() != password.len() * 2 {
+        return Err(invalid_data());
+    }
+
+    Ok(Decoded {
+        password,
+        needs_repair,
+    })
+}
+
+fn invalid_data() -> KeychainError {
+    KeychainError::InvalidData("the credential is not valid UTF-16".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORDS: [&str; 6] = [
+        "Random",
+        "",
+        "🔑🦀 emoji",
+        "密码パスワード비밀번호",
+        // Combining characters, `e` followed by U+0301 and `n` followed by U+0303
+        "cafe\u{301} man\u{303}ana",
+        "mixed ASCII, ümlauts and 😀",
+    ];
+
+    // The blob as written by the old `set_password`, which sized it by the UTF-8 length
+    fn encode_legacy(password: &str) -> Vec<u8> {
+        let mut units: Vec<u16> = password.encode_utf16().collect();
+        units.push(0);
+        let mut blob: Vec<u8> = units.iter().flat_map(|unit| unit.to_le_bytes()).collect();
+        // Whatever followed the string in memory
+        blob.resize(password.len() * 2, 0xAB);
+        blob
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for password in PASSWORDS {
+            let blob = encode(password).unwrap();
+            assert_eq!(blob.len(), password.encode_utf16().count() * 2);
+
+            let decoded = decode(&blob).unwrap();
+            assert_eq!(*decoded.password, password);
+            assert!(!decoded.needs_repair);
+        }
+    }
+
+    #[test]
+    fn test_encode_little_endian() {
+        assert_eq!(*encode("A€").unwrap(), [0x41, 0x00, 0xAC, 0x20]);
+        // Characters outside the BMP are encoded as surrogate pairs
+        assert_eq!(*encode("🔑").unwrap(), [0x3D, 0xD8, 0x11, 0xDD]);
+    }
+
+    #[test]
+    fn test_decode_legacy() {
+        for password in PASSWORDS {
+            let decoded = decode(&encode_legacy(password)).unwrap();
+            assert_eq!(*decoded.password, password);
+
+            // ASCII passwords were sized correctly, so only the others need to be repaired
+            assert_eq!(decoded.needs_repair, !password.is_ascii());
+        }
+    }
+
+    #[test]
+    fn test_error_invalid_blob() {
+        // Odd length
+        assert!(decode(&[0x41, 0x00, 0x42]).is_err());
+        // Unpaired surrogate
+        assert!(decode(&[0x3D, 0xD8, 0x41, 0x00]).is_err());
+        // NUL terminated, but not the size the old format would have
+        assert!(decode(&[0x41, 0x00, 0x00, 0x00, 0x42, 0x00, 0x43, 0x00]).is_err());
+        assert!(encode("nul\0").is_err());
+    }
+}
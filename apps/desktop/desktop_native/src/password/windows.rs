@@ -1,55 +1,180 @@
-use anyhow::{anyhow, Result};
-use widestring::{U16CString, U16String};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use widestring::U16CString;
 use windows::{
     core::{PCWSTR, PWSTR},
     Win32::{
-        Foundation::{GetLastError, ERROR_NOT_FOUND, FILETIME, WIN32_ERROR},
+        Foundation::{
+            GetLastError, ERROR_ACCESS_DENIED, ERROR_NOT_FOUND, ERROR_NO_SUCH_LOGON_SESSION,
+            FILETIME, WIN32_ERROR,
+        },
         Security::Credentials::{
-            CredDeleteW, CredFree, CredReadW, CredWriteW, CREDENTIALW, CRED_FLAGS,
-            CRED_PERSIST_ENTERPRISE, CRED_TYPE_GENERIC,
+            CredDeleteW, CredEnumerateW, CredFree, CredReadW, CredWriteW, CREDENTIALW,
+            CRED_ENUMERATE_FLAGS, CRED_FLAGS, CRED_PERSIST_ENTERPRISE, CRED_TYPE_GENERIC,
         },
     },
 };
+use zeroize::Zeroizing;
+
+use super::{utf16, Account, Entry, EntryMetadata};
+use crate::error::KeychainError;
+
+type Result<T> = std::result::Result<T, KeychainError>;
 
 const CRED_FLAGS_NONE: u32 = 0;
 
-pub fn get_password<'a>(service: &str, account: &str) -> Result<String> {
-    let target_name = U16CString::from_str(target_name(service, account))?;
+/// The largest credential blob `CredWriteW` accepts, `CRED_MAX_CREDENTIAL_BLOB_SIZE`.
+pub const MAX_BLOB_SIZE: usize = 5 * 512;
 
-    let mut credential: *mut CREDENTIALW = std::ptr::null_mut();
-    let credential_ptr = &mut credential;
+// Seconds between the FILETIME epoch, 1601-01-01, and the Unix epoch
+const FILETIME_UNIX_OFFSET: u64 = 11_644_473_600;
+
+pub fn get_password<'a>(service: &str, account: &str) -> Result<Zeroizing<String>> {
+    get_entry(service, account).map(|entry| entry.password)
+}
+
+pub fn get_entry(service: &str, account: &str) -> Result<Entry> {
+    let (decoded, modified, comment) = read_credential(service, account, |credential, blob| {
+        let comment = match credential.Comment.is_null() {
+            true => None,
+            false => Some(unsafe { credential.Comment.to_string() }.map_err(invalid_data)?),
+        };
+        Ok((
+            utf16::decode(blob)?,
+            filetime_to_system_time(credential.LastWritten),
+            comment,
+        ))
+    })?;
+
+    // Rewrite entries stored in the old format. Failing to do so doesn't affect this read, so a
+    // later read can try again
+    if decoded.needs_repair {
+        let blob = utf16::encode(&decoded.password)?;
+        let _ = write_credential(service, account, &blob, comment.as_deref());
+    }
+
+    // Credential Manager has no label and doesn't record when the credential was created
+    Ok(Entry {
+        password: decoded.password,
+        modified,
+        comment,
+        ..Default::default()
+    })
+}
+
+// Remove this after sufficient releases
+pub fn get_password_keytar<'a>(service: &str, account: &str) -> Result<Zeroizing<String>> {
+    read_credential(service, account, |_, blob| {
+        let password = std::str::from_utf8(blob).map_err(invalid_data)?;
+        Ok(Zeroizing::new(String::from(password)))
+    })
+}
+
+pub fn get_secret_bytes(service: &str, account: &str) -> Result<Zeroizing<Vec<u8>>> {
+    read_credential(service, account, |_, blob| {
+        Ok(Zeroizing::new(blob.to_vec()))
+    })
+}
+
+pub fn set_password(service: &str, account: &str, password: &str) -> Result<()> {
+    write_credential(service, account, &utf16::encode(password)?, None)
+}
+
+pub fn set_password_with_metadata(
+    service: &str,
+    account: &str,
+    password: &str,
+    metadata: &EntryMetadata,
+) -> Result<()> {
+    write_credential(
+        service,
+        account,
+        &utf16::encode(password)?,
+        metadata.comment.as_deref(),
+    )
+}
+
+pub fn set_secret_bytes(service: &str, account: &str, secret: &[u8]) -> Result<()> {
+    write_credential(service, account, secret, None)
+}
+
+pub fn delete_password(service: &str, account: &str) -> Result<()> {
+    let target_name = U16CString::from_str(target_name(service, account)).map_err(invalid_data)?;
 
     let result = unsafe {
-        CredReadW(
+        CredDeleteW(
             PCWSTR(target_name.as_ptr()),
             CRED_TYPE_GENERIC.0,
             CRED_FLAGS_NONE,
-            credential_ptr,
+        )
+    };
+    if !result.as_bool() {
+        return Err(convert_error(unsafe { GetLastError() }));
+    }
+
+    Ok(())
+}
+
+pub fn list_accounts(service_prefix: &str) -> Result<Vec<Account>> {
+    let filter = U16CString::from_str(format!("{}*", service_prefix)).map_err(invalid_data)?;
+
+    let mut count = 0;
+    let mut credentials: *mut *mut CREDENTIALW = std::ptr::null_mut();
+
+    let result = unsafe {
+        CredEnumerateW(
+            PCWSTR(filter.as_ptr()),
+            CRED_ENUMERATE_FLAGS(0),
+            &mut count,
+            &mut credentials,
         )
     };
 
     scopeguard::defer!({
-        unsafe { CredFree(credential as *mut _) };
+        unsafe { CredFree(credentials as *mut _) };
     });
 
     if !result.as_bool() {
-        return Err(anyhow!(convert_error(unsafe { GetLastError() })));
+        return match unsafe { GetLastError() } {
+            ERROR_NOT_FOUND => Ok(Vec::new()),
+            code => Err(convert_error(code)),
+        };
     }
 
-    let password = unsafe {
-        U16String::from_ptr(
-            (*credential).CredentialBlob as *const u16,
-            (*credential).CredentialBlobSize as usize / 2,
-        )
-        .to_string_lossy()
-    };
+    let credentials = unsafe { std::slice::from_raw_parts(credentials, count as usize) };
+    let mut accounts = Vec::new();
+    for credential in credentials {
+        let credential = unsafe { &**credential };
+        if credential.Type != CRED_TYPE_GENERIC {
+            continue;
+        }
+
+        let target_name = unsafe { credential.TargetName.to_string() }.map_err(invalid_data)?;
+        if credential.UserName.is_null() {
+            continue;
+        }
+        let account = unsafe { credential.UserName.to_string() }.map_err(invalid_data)?;
+
+        // The target name is `service/account`, and the wildcard filter matches case
+        // insensitively, so check the exact prefix here
+        let service = match target_name.strip_suffix(&format!("/{}", account)) {
+            Some(service) if service.starts_with(service_prefix) => service.to_owned(),
+            _ => continue,
+        };
+        accounts.push(Account { service, account });
+    }
 
-    Ok(String::from(password))
+    Ok(accounts)
 }
 
-// Remove this after sufficient releases
-pub fn get_password_keytar<'a>(service: &str, account: &str) -> Result<String> {
-    let target_name = U16CString::from_str(target_name(service, account))?;
+// Read the credential and pass it with its blob to `f`, which has to copy out what it needs since
+// the credential is freed afterwards
+fn read_credential<T>(
+    service: &str,
+    account: &str,
+    f: impl FnOnce(&CREDENTIALW, &[u8]) -> Result<T>,
+) -> Result<T> {
+    let target_name = U16CString::from_str(target_name(service, account)).map_err(invalid_data)?;
 
     let mut credential: *mut CREDENTIALW = std::ptr::null_mut();
     let credential_ptr = &mut credential;
@@ -68,38 +193,51 @@ pub fn get_password_keytar<'a>(service: &str, account: &str) -> Result<String> {
     });
 
     if !result.as_bool() {
-        return Err(anyhow!(unsafe { GetLastError() }.0.to_string()));
+        return Err(convert_error(unsafe { GetLastError() }));
     }
 
-    let password = unsafe {
-        std::str::from_utf8_unchecked(std::slice::from_raw_parts(
-            (*credential).CredentialBlob,
-            (*credential).CredentialBlobSize as usize,
-        ))
+    let blob = unsafe {
+        match (*credential).CredentialBlob.is_null() {
+            true => &[],
+            false => std::slice::from_raw_parts(
+                (*credential).CredentialBlob,
+                (*credential).CredentialBlobSize as usize,
+            ),
+        }
     };
 
-    Ok(String::from(password))
+    f(unsafe { &*credential }, blob)
 }
 
-pub fn set_password(service: &str, account: &str, password: &str) -> Result<()> {
-    let mut target_name = U16CString::from_str(target_name(service, account))?;
-    let mut user_name = U16CString::from_str(account)?;
+fn write_credential(
+    service: &str,
+    account: &str,
+    blob: &[u8],
+    comment: Option<&str>,
+) -> Result<()> {
+    let mut target_name =
+        U16CString::from_str(target_name(service, account)).map_err(invalid_data)?;
+    let mut user_name = U16CString::from_str(account).map_err(invalid_data)?;
+    let mut comment = comment
+        .map(|comment| U16CString::from_str(comment).map_err(invalid_data))
+        .transpose()?;
+    // Ignored by `CredWriteW`, which records the time itself
     let last_written = FILETIME {
         dwLowDateTime: 0,
         dwHighDateTime: 0,
     };
 
-    let credential = U16CString::from_str(password)?;
-    let credential_len = password.len() as u32 * 2;
-
     let credential = CREDENTIALW {
         Flags: CRED_FLAGS(CRED_FLAGS_NONE),
         Type: CRED_TYPE_GENERIC,
         TargetName: PWSTR(unsafe { target_name.as_mut_ptr() }),
-        Comment: PWSTR::null(),
+        Comment: match comment.as_mut() {
+            Some(comment) => PWSTR(unsafe { comment.as_mut_ptr() }),
+            None => PWSTR::null(),
+        },
         LastWritten: last_written,
-        CredentialBlobSize: credential_len,
-        CredentialBlob: credential.as_ptr() as *mut u8,
+        CredentialBlobSize: blob.len() as u32,
+        CredentialBlob: blob.as_ptr() as *mut u8,
         Persist: CRED_PERSIST_ENTERPRISE,
         AttributeCount: 0,
         Attributes: std::ptr::null_mut(),
@@ -109,39 +247,39 @@ pub fn set_password(service: &str, account: &str, password: &str) -> Result<()>
 
     let result = unsafe { CredWriteW(&credential, 0) };
     if !result.as_bool() {
-        return Err(anyhow!(unsafe { GetLastError() }.0.to_string()));
+        return Err(convert_error(unsafe { GetLastError() }));
     }
 
     Ok(())
 }
 
-pub fn delete_password(service: &str, account: &str) -> Result<()> {
-    let target_name = U16CString::from_str(target_name(service, account))?;
-
-    unsafe {
-        CredDeleteW(
-            PCWSTR(target_name.as_ptr()),
-            CRED_TYPE_GENERIC.0,
-            CRED_FLAGS_NONE,
-        )
-        .ok()?
-    };
-
-    Ok(())
+fn filetime_to_system_time(time: FILETIME) -> Option<SystemTime> {
+    // In 100 nanosecond intervals
+    let intervals = (time.dwHighDateTime as u64) << 32 | time.dwLowDateTime as u64;
+    let since_unix_epoch = Duration::from_nanos(intervals.checked_mul(100)?)
+        .checked_sub(Duration::from_secs(FILETIME_UNIX_OFFSET))?;
+    Some(UNIX_EPOCH + since_unix_epoch)
 }
 
 fn target_name(service: &str, account: &str) -> String {
     format!("{}/{}", service, account)
 }
 
-// Convert the internal WIN32 errors to descriptive messages
-fn convert_error(code: WIN32_ERROR) -> String {
+// Convert the internal WIN32 errors to a `KeychainError`
+fn convert_error(code: WIN32_ERROR) -> KeychainError {
     match code {
-        ERROR_NOT_FOUND => String::from("Password not found."),
-        _ => code.0.to_string(),
+        ERROR_NOT_FOUND => KeychainError::NotFound,
+        ERROR_ACCESS_DENIED => KeychainError::AccessDenied,
+        // The credential manager is not available in this logon session, e.g. for network logons
+        ERROR_NO_SUCH_LOGON_SESSION => KeychainError::BackendUnavailable(code.0.to_string()),
+        _ => KeychainError::Other(code.0.to_string()),
     }
 }
 
+fn invalid_data(error: impl std::fmt::Display) -> KeychainError {
+    KeychainError::InvalidData(error.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -152,24 +290,50 @@ mod tests {
         set_password("BitwardenTest", "BitwardenTest", "Random").unwrap();
         assert_eq!(
             "Random",
-            get_password("BitwardenTest", "BitwardenTest").unwrap()
+            *get_password("BitwardenTest", "BitwardenTest").unwrap()
         );
         delete_password("BitwardenTest", "BitwardenTest").unwrap();
 
         // Ensure password is deleted
         match get_password("BitwardenTest", "BitwardenTest") {
             Ok(_) => panic!("Got a result"),
-            Err(e) => assert_eq!("Password not found.", e.to_string()),
+            Err(e) => assert!(matches!(e, KeychainError::NotFound)),
         }
     }
 
+    #[test]
+    fn test_secret_bytes() {
+        scopeguard::defer!(delete_password("BitwardenTest", "BitwardenTest").unwrap_or({}););
+        let secret = [0u8, 159, 146, 150, 255];
+        set_secret_bytes("BitwardenTest", "BitwardenTest", &secret).unwrap();
+        assert_eq!(
+            *get_secret_bytes("BitwardenTest", "BitwardenTest").unwrap(),
+            secret
+        );
+    }
+
+    #[test]
+    fn test_entry_metadata() {
+        scopeguard::defer!(delete_password("BitwardenTest", "BitwardenTest").unwrap_or({}););
+        let metadata = EntryMetadata {
+            label: None,
+            comment: Some("Configured on this device".to_owned()),
+        };
+        set_password_with_metadata("BitwardenTest", "BitwardenTest", "Random", &metadata).unwrap();
+
+        let entry = get_entry("BitwardenTest", "BitwardenTest").unwrap();
+        assert_eq!(*entry.password, "Random");
+        assert_eq!(entry.comment, metadata.comment);
+        assert!(entry.modified.unwrap() <= SystemTime::now());
+    }
+
     #[test]
     fn test_get_password_keytar() {
         scopeguard::defer!(delete_password("BitwardenTest", "BitwardenTest").unwrap_or({}););
         keytar::set_password("BitwardenTest", "BitwardenTest", "HelloFromKeytar").unwrap();
         assert_eq!(
             "HelloFromKeytar",
-            get_password_keytar("BitwardenTest", "BitwardenTest").unwrap()
+            *get_password_keytar("BitwardenTest", "BitwardenTest").unwrap()
         );
     }
 
@@ -177,7 +341,7 @@ mod tests {
     fn test_error_no_password() {
         match get_password("BitwardenTest", "BitwardenTest") {
             Ok(_) => panic!("Got a result"),
-            Err(e) => assert_eq!("Password not found.", e.to_string()),
+            Err(e) => assert!(matches!(e, KeychainError::NotFound)),
         }
     }
 }
@@ -38,23 +38,10 @@ export default class BiometricDarwinMain implements OsBiometricService {
   }
 
   async setBiometricKey(service: string, key: string, value: string): Promise<void> {
-    if (await this.valueUpToDate(service, key, value)) {
-      return;
-    }
-
-    return await passwords.setPassword(service, key, value);
+    await passwords.setPasswordIfChanged(service, key, value);
   }
 
   async deleteBiometricKey(service: string, key: string): Promise<void> {
     return await passwords.deletePassword(service, key);
   }
-
-  private async valueUpToDate(service: string, key: string, value: string): Promise<boolean> {
-    try {
-      const existing = await passwords.getPassword(service, key);
-      return existing === value;
-    } catch {
-      return false;
-    }
-  }
 }
@@ -31,7 +31,7 @@ export default class BiometricWindowsMain implements OsBiometricService {
   }
 
   async osSupportsBiometric(): Promise<boolean> {
-    return await biometrics.available();
+    return (await biometrics.available()).available;
   }
 
   async getBiometricKey(
@@ -63,7 +63,11 @@ export default class BiometricWindowsMain implements OsBiometricService {
       const storageDetails = await this.getStorageDetails({
         clientKeyHalfB64,
       });
-      return await biometrics.getBiometricSecret(service, storageKey, storageDetails.key_material);
+      // The value was checked to be encrypted, so refuse it if it was replaced by plaintext since
+      return await biometrics.getBiometricSecret(service, storageKey, storageDetails.key_material, {
+        ivB64: storageDetails.ivB64,
+        strict: true,
+      });
     }
   }
 
@@ -103,7 +107,8 @@ export default class BiometricWindowsMain implements OsBiometricService {
 
   async authenticateBiometric(): Promise<boolean> {
     const hwnd = this.windowMain.win.getNativeWindowHandle();
-    return await biometrics.prompt(hwnd, this.i18nService.t("windowsHelloConsentMessage"));
+    const outcome = await biometrics.prompt(hwnd, this.i18nService.t("windowsHelloConsentMessage"));
+    return outcome === biometrics.PromptOutcome.Verified;
   }
 
   private async getStorageDetails({
@@ -193,10 +198,13 @@ export default class BiometricWindowsMain implements OsBiometricService {
 
     let witness = null;
     try {
+      // A witness stored unencrypted proves nothing about the key, so it is refused rather than
+      // upgraded, which also means no IV is needed
       witness = await biometrics.getBiometricSecret(
         service,
         storageKey + KEY_WITNESS_SUFFIX,
-        witnessKeyMaterial
+        witnessKeyMaterial,
+        { strict: true }
       );
     } catch {
       this.logService.debug("Error retrieving witness key, assuming value is not up to date.");
@@ -41,10 +41,7 @@ export class DesktopCredentialStorageListener {
         }
         return val;
       } catch (e) {
-        if (
-          e.message === "Password not found." ||
-          e.message === "The specified item could not be found in the keychain."
-        ) {
+        if (e.code === passwords.KeychainErrorCode.NotFound) {
           return null;
         }
         this.logService.info(e);